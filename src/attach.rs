@@ -0,0 +1,349 @@
+// `@path` and `@path:10-40` in prompts attach files as fenced code blocks
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+
+// Files larger than this aren't read at all
+const MAX_FILE: u64 = 4 * 1024 * 1024;
+// Most text a single attachment can add to the prompt
+const MAX_ATTACHMENT: usize = 100 * 1024;
+// Punctuation that ends a sentence rather than a path
+const TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+/// A file reference found in a prompt
+#[derive(PartialEq, Debug)]
+struct Reference {
+    path: String,
+    // First and last line, counting from 1
+    lines: Option<(usize, usize)>,
+}
+
+/// The path with `~/` replaced by the home directory
+pub fn resolve(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Splits `path:10-40` or `path:7`, the path has to exist
+fn parse(token: &str) -> Option<Reference> {
+    let existing = |path: &str| !path.is_empty() && resolve(path).is_file();
+    let mut token = token;
+    loop {
+        if let Some((path, range)) = token.rsplit_once(':') {
+            let lines = match range.split_once('-') {
+                Some((start, end)) => start.parse().ok().zip(end.parse().ok()),
+                None => range.parse().ok().map(|line| (line, line)),
+            };
+            if lines.is_some() && existing(path) {
+                return Some(Reference {
+                    path: path.to_string(),
+                    lines,
+                });
+            }
+        }
+        if existing(token) {
+            return Some(Reference {
+                path: token.to_string(),
+                lines: None,
+            });
+        }
+        token = token.strip_suffix(TRAILING)?;
+    }
+}
+
+// Longest run of backticks in the text, the fence has to be longer
+pub(crate) fn fence(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat((longest + 1).max(3))
+}
+
+impl Reference {
+    fn label(&self) -> String {
+        match self.lines {
+            Some((start, end)) if start == end => format!("{} line {}", self.path, start),
+            Some((start, end)) => format!("{} lines {}-{}", self.path, start, end),
+            None => self.path.clone(),
+        }
+    }
+
+    fn read(&self) -> Result<String, String> {
+        let path = resolve(&self.path);
+        let error = |err: std::io::Error| format!("{}: {}", self.path, err);
+        if fs::metadata(&path).map_err(error)?.len() > MAX_FILE {
+            return Err(format!("{} is too large", self.path));
+        }
+        let bytes = fs::read(&path).map_err(error)?;
+        if bytes.contains(&0) {
+            return Err(format!("{} is a binary file", self.path));
+        }
+        let text =
+            String::from_utf8(bytes).map_err(|_| format!("{} is a binary file", self.path))?;
+
+        let text = match self.lines {
+            Some((start, end)) => {
+                let count = text.lines().count();
+                if start == 0 || start > end || start > count {
+                    return Err(format!(
+                        "{} has no lines {}-{}, it has {}",
+                        self.path, start, end, count
+                    ));
+                }
+                let lines: Vec<&str> = text.lines().skip(start - 1).take(end - start + 1).collect();
+                lines.join("\n")
+            }
+            None => text.trim_end_matches('\n').to_string(),
+        };
+        if text.len() > MAX_ATTACHMENT {
+            return Err(format!(
+                "{} is too large, pick some lines with {}:start-end",
+                self.label(),
+                self.path
+            ));
+        }
+        Ok(text)
+    }
+
+    fn block(&self, text: &str) -> String {
+        let fence = fence(text);
+        let language = self.path.rsplit_once('.').map_or("", |(_, ext)| ext);
+        format!(
+            "[attached {}]\n{}{}\n{}\n{}",
+            self.label(),
+            fence,
+            language,
+            text,
+            fence
+        )
+    }
+}
+
+/// Adds the files referenced with `@` as code blocks after the prompt, the
+/// references become the plain paths. Words like `@someone` that aren't files stay.
+pub fn expand(prompt: &str) -> Result<String, String> {
+    let mut text = String::new();
+    let mut blocks = vec![];
+    for word in prompt.split_inclusive(char::is_whitespace) {
+        match word.trim_end().strip_prefix('@').and_then(parse) {
+            Some(reference) => {
+                let block = reference.block(&reference.read()?);
+                if !blocks.contains(&block) {
+                    blocks.push(block);
+                }
+                text.push_str(&word[1..]);
+            }
+            None => text.push_str(word),
+        }
+    }
+    for block in blocks {
+        text.push_str("\n\n");
+        text.push_str(&block);
+    }
+    Ok(text)
+}
+
+/// Shows attached blocks as a single line with their size
+pub fn preview(content: &str) -> Cow<'_, str> {
+    if !content.contains("\n[attached ") && !content.starts_with("[attached ") {
+        return Cow::Borrowed(content);
+    }
+    let mut lines = content.split('\n').peekable();
+    let mut preview = vec![];
+    while let Some(line) = lines.next() {
+        let fence = lines
+            .peek()
+            .map(|next| next.len() - next.trim_start_matches('`').len())
+            .unwrap_or(0);
+        match line
+            .strip_prefix("[attached ")
+            .and_then(|l| l.strip_suffix(']'))
+        {
+            Some(label) if fence >= 3 => {
+                let fence = "`".repeat(fence);
+                lines.next();
+                let count = lines.by_ref().take_while(|l| *l != fence).count();
+                let plural = if count == 1 { "" } else { "s" };
+                preview.push(format!("[attached {}, {} line{}]", label, count, plural));
+            }
+            _ => preview.push(line.to_string()),
+        }
+    }
+    Cow::Owned(preview.join("\n"))
+}
+
+/// Completes the path after an `@`, returns the longest completion shared by
+/// every match and the matching names when there are several
+pub fn complete(partial: &str) -> (String, Vec<String>) {
+    let (dir, prefix) = match partial.rsplit_once('/') {
+        Some((dir, prefix)) => (format!("{}/", dir), prefix),
+        None => (String::new(), partial),
+    };
+    let read = match dir.as_str() {
+        "" => fs::read_dir("."),
+        dir => fs::read_dir(resolve(dir)),
+    };
+    let mut matches: Vec<(String, bool)> = read
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            let hidden = name.starts_with('.') && !prefix.starts_with('.');
+            (!hidden && name.starts_with(prefix)).then(|| (name, entry.path().is_dir()))
+        })
+        .collect();
+    matches.sort();
+
+    let completion = match matches.as_slice() {
+        [] => return (partial.to_string(), vec![]),
+        [(name, true)] => format!("{}{}/", dir, name),
+        [(name, false)] => format!("{}{}", dir, name),
+        [(first, _), rest @ ..] => {
+            let mut common = first.clone();
+            for (name, _) in rest {
+                while !name.starts_with(&common) {
+                    common.pop();
+                }
+            }
+            format!("{}{}", dir, common)
+        }
+    };
+    let names = match matches.len() {
+        1 => vec![],
+        _ => matches
+            .into_iter()
+            .map(|(name, dir)| if dir { format!("{}/", name) } else { name })
+            .collect(),
+    };
+    (completion, names)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attach::{complete, expand, parse, preview, Reference};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gpterm-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn references() {
+        let dir = dir("references");
+        let file = dir.join("main.rs");
+        fs::write(&file, "fn main() {}\n").unwrap();
+        let path = file.to_str().unwrap();
+
+        let reference = |path: &str, lines| Reference {
+            path: path.to_string(),
+            lines,
+        };
+        assert_eq!(parse(path), Some(reference(path, None)));
+        assert_eq!(
+            parse(&format!("{}:3-9", path)),
+            Some(reference(path, Some((3, 9))))
+        );
+        assert_eq!(
+            parse(&format!("{}:4", path)),
+            Some(reference(path, Some((4, 4))))
+        );
+        // The end of a sentence isn't part of the path
+        assert_eq!(parse(&format!("{}.", path)), Some(reference(path, None)));
+        assert_eq!(
+            parse(&format!("{}:2-3).", path)),
+            Some(reference(path, Some((2, 3))))
+        );
+        assert_eq!(parse("someone"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn attach() {
+        let dir = dir("attach");
+        let file = dir.join("lib.rs");
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+        let path = file.to_str().unwrap();
+
+        let text = expand(&format!("Explain @{} to @someone", path)).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "Explain {0} to @someone\n\n[attached {0}]\n```rs\none\ntwo\nthree\n```",
+                path
+            )
+        );
+        assert_eq!(
+            preview(&text),
+            format!("Explain {0} to @someone\n\n[attached {0}, 3 lines]", path)
+        );
+
+        let text = expand(&format!("@{}:2-3", path)).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "{0}:2-3\n\n[attached {0} lines 2-3]\n```rs\ntwo\nthree\n```",
+                path
+            )
+        );
+        assert!(expand(&format!("@{}:4-5", path)).is_err());
+
+        // Fences stay longer than the backticks in the file
+        let markdown = dir.join("notes.md");
+        fs::write(&markdown, "```sh\nls\n```").unwrap();
+        let text = expand(&format!("@{}", markdown.to_str().unwrap())).unwrap();
+        assert!(text.ends_with("\n````md\n```sh\nls\n```\n````"));
+        assert!(preview(&text).ends_with("notes.md, 3 lines]"));
+
+        let binary = dir.join("image.png");
+        fs::write(&binary, [137, 80, 78, 71, 0, 1]).unwrap();
+        let error = expand(&format!("@{}", binary.to_str().unwrap())).unwrap_err();
+        assert!(error.ends_with("is a binary file"));
+
+        let large = dir.join("large.txt");
+        fs::write(&large, "x".repeat(200 * 1024)).unwrap();
+        let error = expand(&format!("@{}", large.to_str().unwrap())).unwrap_err();
+        assert!(error.contains("is too large"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn completion() {
+        let dir = dir("complete");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("setup.py"), "").unwrap();
+        fs::write(dir.join("src/chat.rs"), "").unwrap();
+        fs::write(dir.join("src/clipboard.rs"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let base = format!("{}/", dir.to_str().unwrap());
+
+        assert_eq!(
+            complete(&format!("{}sr", base)),
+            (format!("{}src/", base), vec![])
+        );
+        assert_eq!(
+            complete(&format!("{}src/c", base)),
+            (
+                format!("{}src/c", base),
+                vec!["chat.rs".to_string(), "clipboard.rs".to_string()]
+            )
+        );
+        assert_eq!(
+            complete(&format!("{}src/cl", base)),
+            (format!("{}src/clipboard.rs", base), vec![])
+        );
+        let (_, names) = complete(&base);
+        assert_eq!(names, ["setup.py", "src/"]);
+        assert_eq!(
+            complete(&format!("{}x", base)),
+            (format!("{}x", base), vec![])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
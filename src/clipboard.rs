@@ -0,0 +1,129 @@
+// Clipboard access that works without a display server: the system clipboard when
+// there is one, the terminal's OSC 52 support over SSH and a private one otherwise
+
+use crate::config::ClipboardKind;
+use std::io::{self, IsTerminal, Write};
+
+pub trait Clipboard {
+    // None when there is nothing to paste
+    fn get_text(&mut self) -> Option<String>;
+    fn set_text(&mut self, text: &str) -> Result<(), String>;
+}
+
+/// The desktop clipboard through arboard
+pub struct System(arboard::Clipboard);
+
+impl Clipboard for System {
+    fn get_text(&mut self) -> Option<String> {
+        self.0.get_text().ok()
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.0.set_text(text).map_err(|err| err.to_string())
+    }
+}
+
+/// Copies through the terminal with OSC 52, which also works over SSH.
+/// Terminals rarely let apps read their clipboard, so pasting gives the last copy.
+#[derive(Default)]
+pub struct Osc52 {
+    last: Option<String>,
+}
+
+impl Clipboard for Osc52 {
+    fn get_text(&mut self) -> Option<String> {
+        self.last.clone()
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        let mut stdout = io::stdout();
+        write!(stdout, "{}", osc52(text))
+            .and_then(|_| stdout.flush())
+            .map_err(|err| err.to_string())?;
+        self.last = Some(text.to_string());
+        Ok(())
+    }
+}
+
+/// Only shared inside the app, used when nothing else works and by tests
+#[derive(Default)]
+pub struct Memory {
+    text: Option<String>,
+}
+
+impl Clipboard for Memory {
+    fn get_text(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.text = Some(text.to_string());
+        Ok(())
+    }
+}
+
+/// The configured clipboard, auto picks the first one that works here
+pub fn select(kind: ClipboardKind) -> Box<dyn Clipboard> {
+    let system = || arboard::Clipboard::new().ok().map(System);
+    match kind {
+        ClipboardKind::Auto => match system() {
+            Some(system) => Box::new(system),
+            None if io::stdout().is_terminal() => Box::<Osc52>::default(),
+            None => Box::<Memory>::default(),
+        },
+        ClipboardKind::System => match system() {
+            Some(system) => Box::new(system),
+            None => Box::<Memory>::default(),
+        },
+        ClipboardKind::Osc52 => Box::<Osc52>::default(),
+        ClipboardKind::Memory => Box::<Memory>::default(),
+    }
+}
+
+// Sequence asking the terminal to set its clipboard
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for group in bytes.chunks(3) {
+        let n = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clipboard::{base64, osc52, Clipboard, Memory};
+
+    #[test]
+    fn encode() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("héllo".as_bytes()), "aMOpbGxv");
+        assert_eq!(osc52("hi"), "\x1b]52;c;aGk=\x07");
+    }
+
+    #[test]
+    fn memory() {
+        let mut clipboard = Memory::default();
+        assert_eq!(clipboard.get_text(), None);
+        clipboard.set_text("copied").unwrap();
+        assert_eq!(clipboard.get_text(), Some("copied".to_string()));
+    }
+}
@@ -0,0 +1,188 @@
+// Prompts starting with `/` change the chat instead of being sent, `//` sends a slash
+
+/// A slash command with its arguments
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    // None goes back to the default
+    Model(Option<String>),
+    // None removes the system prompt
+    System(Option<String>),
+    Title(String),
+    Clear,
+    Export(String),
+    Temp(Option<f32>),
+    Retry,
+    Help,
+}
+
+/// Every command with its arguments and help text
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    ("model", "[name]", "Switch model, none uses the default"),
+    ("system", "[prompt]", "Set or remove the system prompt"),
+    ("title", "<title>", "Rename the chat"),
+    ("clear", "", "Clear messages, keep the system one"),
+    ("export", "<path>", "Save the chat as Markdown"),
+    ("temp", "[0-2]", "Set the temperature, none resets"),
+    ("retry", "", "Ask for the last answer again"),
+    ("help", "", "Show the keys and commands"),
+];
+
+// The command name and its arguments, None when the prompt isn't a command
+fn split(prompt: &str) -> Option<(&str, &str)> {
+    let rest = prompt.strip_prefix('/')?;
+    if rest.starts_with('/') {
+        return None;
+    }
+    Some(match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    })
+}
+
+/// Reads a slash command, None when the prompt is to be sent
+pub fn parse(prompt: &str) -> Option<Result<Command, String>> {
+    let (name, args) = split(prompt.trim_start())?;
+    let arg = (!args.is_empty()).then(|| args.to_string());
+    let needs = |hint: &str| format!("/{} needs {}", name, hint);
+    Some(match name {
+        "model" => Ok(Command::Model(arg)),
+        "system" => Ok(Command::System(arg)),
+        "title" => arg.map(Command::Title).ok_or_else(|| needs("a title")),
+        "export" => arg.map(Command::Export).ok_or_else(|| needs("a path")),
+        "temp" => match arg.map(|t| t.parse::<f32>()) {
+            None => Ok(Command::Temp(None)),
+            Some(Ok(temp)) if (0.0..=2.0).contains(&temp) => Ok(Command::Temp(Some(temp))),
+            Some(_) => Err("/temp takes a number from 0 to 2".to_string()),
+        },
+        "clear" | "retry" | "help" if arg.is_some() => {
+            Err(format!("/{} doesn't take arguments", name))
+        }
+        "clear" => Ok(Command::Clear),
+        "retry" => Ok(Command::Retry),
+        "help" => Ok(Command::Help),
+        _ => Err(format!("Unknown command /{}, /help lists them", name)),
+    })
+}
+
+// Commands starting with the typed name
+fn matching(name: &str) -> Vec<&'static (&'static str, &'static str, &'static str)> {
+    COMMANDS
+        .iter()
+        .filter(|(n, _, _)| n.starts_with(name))
+        .collect()
+}
+
+/// Completes the command name, returns the new prompt and the names to pick
+/// from when several match
+pub fn complete(prompt: &str) -> Option<(String, Vec<String>)> {
+    let (name, _) = split(prompt).filter(|_| !prompt.contains(char::is_whitespace))?;
+    let matches = matching(name);
+    match matches.as_slice() {
+        [] => None,
+        [(name, _, _)] => Some((format!("/{} ", name), vec![])),
+        [(first, _, _), rest @ ..] => {
+            let mut common = first.to_string();
+            for (name, _, _) in rest {
+                while !name.starts_with(&common) {
+                    common.pop();
+                }
+            }
+            let names = matches.iter().map(|(n, _, _)| format!("/{}", n)).collect();
+            Some((format!("/{}", common), names))
+        }
+    }
+}
+
+/// Rest of the command and its arguments, shown greyed after what is typed
+pub fn hint(prompt: &str) -> Option<String> {
+    let (name, args) = split(prompt)?;
+    let typed_args = prompt.contains(char::is_whitespace);
+    match matching(name).as_slice() {
+        [(full, hint, _)] if !typed_args => {
+            let space = if hint.is_empty() { "" } else { " " };
+            Some(format!("{}{}{}", &full[name.len()..], space, hint))
+        }
+        _ if typed_args && args.is_empty() => COMMANDS
+            .iter()
+            .find(|(n, hint, _)| *n == name && !hint.is_empty())
+            .map(|(_, hint, _)| {
+                let space = if prompt.ends_with(char::is_whitespace) {
+                    ""
+                } else {
+                    " "
+                };
+                format!("{}{}", space, hint)
+            }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{complete, hint, parse, Command};
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse("hello"), None);
+        assert_eq!(parse("//etc is a path"), None);
+        assert_eq!(
+            parse("/model gpt-4"),
+            Some(Ok(Command::Model(Some("gpt-4".to_string()))))
+        );
+        assert_eq!(parse("/model"), Some(Ok(Command::Model(None))));
+        assert_eq!(
+            parse("/system  Be brief. "),
+            Some(Ok(Command::System(Some("Be brief.".to_string()))))
+        );
+        assert_eq!(parse("/temp 0.5"), Some(Ok(Command::Temp(Some(0.5)))));
+        assert_eq!(parse("/retry"), Some(Ok(Command::Retry)));
+
+        assert_eq!(
+            parse("/title"),
+            Some(Err("/title needs a title".to_string()))
+        );
+        assert_eq!(
+            parse("/temp 3"),
+            Some(Err("/temp takes a number from 0 to 2".to_string()))
+        );
+        assert_eq!(
+            parse("/clear all"),
+            Some(Err("/clear doesn't take arguments".to_string()))
+        );
+        assert_eq!(
+            parse("/usr/bin is empty"),
+            Some(Err("Unknown command /usr/bin, /help lists them".to_string()))
+        );
+    }
+
+    #[test]
+    fn completion() {
+        assert_eq!(complete("/ti"), Some(("/title ".to_string(), vec![])));
+        assert_eq!(
+            complete("/"),
+            Some((
+                "/".to_string(),
+                ["/model", "/system", "/title", "/clear", "/export", "/temp", "/retry", "/help"]
+                    .map(String::from)
+                    .to_vec()
+            ))
+        );
+        assert_eq!(
+            complete("/t"),
+            Some((
+                "/t".to_string(),
+                vec!["/title".to_string(), "/temp".to_string()]
+            ))
+        );
+        assert_eq!(complete("/title x"), None);
+        assert_eq!(complete("/x"), None);
+
+        assert_eq!(hint("/exp"), Some("ort <path>".to_string()));
+        assert_eq!(hint("/export"), Some(" <path>".to_string()));
+        assert_eq!(hint("/export "), Some("<path>".to_string()));
+        assert_eq!(hint("/export out.md"), None);
+        assert_eq!(hint("/cl"), Some("ear".to_string()));
+        assert_eq!(hint("/t"), None);
+        assert_eq!(hint("hi"), None);
+    }
+}
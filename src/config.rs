@@ -0,0 +1,170 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::{fs, io};
+
+/// User settings read from `config.toml`, every field is optional
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    // Key overrides as context -> action -> keys
+    pub keys: HashMap<String, HashMap<String, Vec<String>>>,
+    // Built in or user theme name
+    pub theme: Option<String>,
+    // Provider profiles by name
+    pub providers: BTreeMap<String, ProviderConfig>,
+    // Profile used by chats that don't pick one
+    pub provider: Option<String>,
+    pub clipboard: ClipboardKind,
+    // Vim style modal editing in the chat prompt
+    pub vim: bool,
+    // Lets OpenAI compatible models call the built in tools
+    pub tools: bool,
+}
+
+/// Where copies go and pastes come from
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardKind {
+    // The system clipboard, OSC 52 when there is none and the output is a terminal
+    #[default]
+    Auto,
+    System,
+    Osc52,
+    // Kept inside the app
+    Memory,
+}
+
+/// A `[providers.<name>]` profile, keys can also come from the environment
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ProviderConfig {
+    OpenAi {
+        api_key: Option<String>,
+        // Variable holding the key, OPENAI_API_KEY by default
+        api_key_env: Option<String>,
+        base_url: Option<String>,
+        model: Option<String>,
+    },
+    Azure {
+        // https://<resource>.openai.azure.com
+        endpoint: String,
+        deployment: String,
+        api_version: Option<String>,
+        api_key: Option<String>,
+        // Variable holding the key, AZURE_OPENAI_API_KEY by default
+        api_key_env: Option<String>,
+    },
+    // OpenAI compatible servers like llama.cpp, vLLM or Ollama's /v1
+    Local {
+        base_url: String,
+        api_key: Option<String>,
+        model: Option<String>,
+    },
+    // Ollama's native /api/chat
+    Ollama {
+        // http://localhost:11434 by default
+        base_url: Option<String>,
+        model: String,
+    },
+    // Anthropic's messages API
+    Anthropic {
+        api_key: Option<String>,
+        // Variable holding the key, ANTHROPIC_API_KEY by default
+        api_key_env: Option<String>,
+        // https://api.anthropic.com by default
+        base_url: Option<String>,
+        model: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Keymap(String),
+    Theme(String),
+    Provider(String),
+    Persona(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config: {}", err),
+            ConfigError::Parse(err) => write!(f, "invalid config: {}", err),
+            ConfigError::Keymap(err) => write!(f, "invalid key bindings: {}", err),
+            ConfigError::Theme(err) => write!(f, "invalid theme: {}", err),
+            ConfigError::Provider(err) => write!(f, "invalid provider: {}", err),
+            ConfigError::Persona(err) => write!(f, "invalid persona: {}", err),
+        }
+    }
+}
+
+/// Directory holding every config file
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("gpterm"))
+}
+
+/// Directory for files the app writes itself, like crash reports
+pub fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("gpterm"))
+}
+
+impl Config {
+    /// Loads the config file, a missing file means the default config
+    pub fn load() -> Result<Self, ConfigError> {
+        match dir().map(|dir| dir.join("config.toml")) {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+                toml::from_str(&text).map_err(ConfigError::Parse)
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ProviderConfig};
+
+    #[test]
+    fn providers() {
+        let config: Config = toml::from_str(
+            r#"
+            provider = "work"
+
+            [providers.work]
+            kind = "azure"
+            endpoint = "https://example.openai.azure.com"
+            deployment = "gpt35"
+
+            [providers.llama]
+            kind = "local"
+            base_url = "http://localhost:8080/v1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.provider.as_deref(), Some("work"));
+        assert_eq!(
+            config.providers["llama"],
+            ProviderConfig::Local {
+                base_url: "http://localhost:8080/v1".to_string(),
+                api_key: None,
+                model: None,
+            }
+        );
+        assert!(matches!(
+            config.providers["work"],
+            ProviderConfig::Azure { .. }
+        ));
+
+        // Typos are reported instead of ignored
+        assert!(toml::from_str::<Config>("[providers.x]\nkind = \"local\"\nbase = \"\"").is_err());
+        assert!(toml::from_str::<Config>("[providers.x]\nkind = \"cloud\"").is_err());
+    }
+}
@@ -1,128 +1,621 @@
-use crate::prompt::{Prompt, StaticPrompt};
-use crate::{popup, Window};
-use arboard::Clipboard;
-use crossterm::event::{KeyCode, KeyEvent};
+use crate::chat::{ChatSettings, ModelParams};
+use crate::clipboard::Clipboard;
+use crate::keymap::{self, keymap, Context};
+use crate::persona::{Persona, Personas, Seed};
+use crate::prompt::{Prompt, StaticPrompt, WrapPrompt};
+use crate::theme::theme;
+use crate::{contains, popup, Window};
+use crossterm::event::{KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use std::fs;
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    widgets::{Block, Borders, Paragraph},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Style,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
+#[derive(Clone, Copy, PartialEq)]
 enum Focus {
+    Personas,
     Title,
     System,
+    Provider,
+    Model,
+    Temperature,
+    MaxTokens,
 }
 
 pub enum Action {
     Quit,
-    New { title: String, system: String },
+    New {
+        title: String,
+        system: String,
+        params: ModelParams,
+        seed: Vec<Seed>,
+    },
+    // Changes to the current chat
+    Update(ChatSettings),
 }
 
-/// Chat creation window
+/// Chat creation window, also used to edit the current chat
 pub struct Creator {
     focus: Focus,
+    // Some while editing a chat, holds whether it has a system message
+    editing: Option<bool>,
+    personas: Personas,
+    // Provider profile names, an empty field uses the default one
+    providers: Vec<String>,
+    // Persona the fields were filled from
+    selected: Option<usize>,
     title: StaticPrompt,
-    system: StaticPrompt,
+    system: WrapPrompt,
+    provider: StaticPrompt,
+    model: StaticPrompt,
+    temperature: StaticPrompt,
+    max_tokens: StaticPrompt,
+    // Path of a file to load the system prompt from, open while typing it
+    file: Option<StaticPrompt>,
+    // Feedback shown over the persona list
+    message: Option<String>,
+    // Last known screen size, used to place mouse clicks
+    size: Rect,
+}
+
+// Most rows the system field grows to before it scrolls
+const SYSTEM_ROWS: usize = 10;
+
+// Replaces the prompt text and leaves the cursor at the end
+fn fill(prompt: &mut dyn Prompt, text: &str) {
+    prompt.flush();
+    prompt.add_str(text);
 }
 
 impl Creator {
-    pub fn new() -> Self {
+    pub fn new(personas: Personas, providers: Vec<String>) -> Self {
         Self {
             focus: Focus::Title,
+            editing: None,
+            personas,
+            providers,
+            selected: None,
             title: StaticPrompt::new(),
-            system: StaticPrompt::new(),
+            system: WrapPrompt::new(0, 0),
+            provider: StaticPrompt::new(),
+            model: StaticPrompt::new(),
+            temperature: StaticPrompt::new(),
+            max_tokens: StaticPrompt::new(),
+            file: None,
+            message: None,
+            size: Rect::default(),
+        }
+    }
+
+    /// Contexts receiving keys, the first one is the most specific
+    pub fn contexts(&self) -> Vec<Context> {
+        match self.focus {
+            _ if self.file.is_some() => vec![Context::Creator, Context::Prompt],
+            Focus::Personas => vec![Context::Creator, Context::Personas],
+            _ => vec![Context::Creator, Context::Prompt],
         }
     }
 
-    fn flush(&mut self) -> Action {
-        Action::New {
-            title: self.title.flush(),
-            system: self.system.flush(),
+    fn params(&self) -> Result<ModelParams, String> {
+        let provider = self.provider.str().trim();
+        if !provider.is_empty() && !self.providers.iter().any(|p| p == provider) {
+            return Err(format!(
+                "Unknown provider, pick one of {}",
+                self.providers.join(", ")
+            ));
+        }
+        let model = self.model.str().trim();
+        let temperature = match self.temperature.str().trim() {
+            "" => None,
+            t => Some(
+                t.parse::<f32>()
+                    .ok()
+                    .filter(|t| (0.0..=2.0).contains(t))
+                    .ok_or("Temperature must be between 0 and 2")?,
+            ),
+        };
+        let max_tokens = match self.max_tokens.str().trim() {
+            "" => None,
+            t => Some(
+                t.parse::<u16>()
+                    .map_err(|_| "Max tokens must be a whole number")?,
+            ),
+        };
+
+        Ok(ModelParams {
+            provider: Some(provider.to_string()).filter(|p| !p.is_empty()),
+            model: Some(model.to_string()).filter(|m| !m.is_empty()),
+            temperature,
+            max_tokens,
+            ..ModelParams::default()
+        })
+    }
+
+    fn fill_params(&mut self, params: &ModelParams) {
+        fill(&mut self.provider, params.provider.as_deref().unwrap_or(""));
+        fill(&mut self.model, params.model.as_deref().unwrap_or(""));
+        fill(
+            &mut self.temperature,
+            &params.temperature.map_or(String::new(), |t| t.to_string()),
+        );
+        fill(
+            &mut self.max_tokens,
+            &params.max_tokens.map_or(String::new(), |t| t.to_string()),
+        );
+    }
+
+    fn seed(&self) -> Vec<Seed> {
+        self.selected
+            .and_then(|i| self.personas.list.get(i))
+            .map(|persona| persona.seed.clone())
+            .unwrap_or_default()
+    }
+
+    /// Fills the fields from the current chat, submitting then updates it
+    pub fn edit(&mut self, settings: ChatSettings) {
+        self.reset();
+        self.editing = Some(settings.system.is_some());
+        fill(&mut self.title, &settings.title);
+        fill(&mut self.system, settings.system.as_deref().unwrap_or(""));
+        self.fill_params(&settings.params);
+        self.resize();
+    }
+
+    // None when a field is invalid, the popup then stays open to show why
+    fn flush(&mut self) -> Option<Action> {
+        match self.params() {
+            Ok(params) => {
+                let title = self.title.str().to_string();
+                let system = self.system.str().to_string();
+                let action = match self.editing {
+                    Some(_) => Action::Update(ChatSettings {
+                        title,
+                        system: Some(system).filter(|s| !s.is_empty()),
+                        params,
+                    }),
+                    None => Action::New {
+                        title,
+                        system,
+                        params,
+                        seed: self.seed(),
+                    },
+                };
+                self.reset();
+                Some(action)
+            }
+            Err(err) => {
+                self.message = Some(err);
+                None
+            }
         }
     }
 
     fn reset(&mut self) {
         self.focus = Focus::Title;
+        self.editing = None;
+        self.selected = None;
         self.title = StaticPrompt::new();
-        self.system = StaticPrompt::new();
+        self.system.flush();
+        self.provider = StaticPrompt::new();
+        self.model = StaticPrompt::new();
+        self.temperature = StaticPrompt::new();
+        self.max_tokens = StaticPrompt::new();
+        self.file = None;
+        self.message = None;
+        self.resize();
     }
 
     fn next(&mut self) {
-        match self.focus {
-            Focus::Title => self.focus = Focus::System,
-            Focus::System => self.focus = Focus::Title,
+        self.focus = match self.focus {
+            Focus::Personas => Focus::Title,
+            Focus::Title => Focus::System,
+            Focus::System => Focus::Provider,
+            Focus::Provider => Focus::Model,
+            Focus::Model => Focus::Temperature,
+            Focus::Temperature => Focus::MaxTokens,
+            Focus::MaxTokens => Focus::Personas,
         }
     }
 
-    fn current_prompt(&mut self) -> &mut StaticPrompt {
+    fn current_prompt(&mut self) -> Option<&mut dyn Prompt> {
         match self.focus {
-            Focus::Title => &mut self.title,
-            Focus::System => &mut self.system,
+            Focus::Personas => None,
+            Focus::Title => Some(&mut self.title),
+            Focus::System => Some(&mut self.system),
+            Focus::Provider => Some(&mut self.provider),
+            Focus::Model => Some(&mut self.model),
+            Focus::Temperature => Some(&mut self.temperature),
+            Focus::MaxTokens => Some(&mut self.max_tokens),
         }
     }
+
+    // Fills every field from the persona
+    fn select(&mut self, index: usize) {
+        if let Some(persona) = self.personas.list.get(index).cloned() {
+            self.selected = Some(index);
+            fill(&mut self.title, &persona.name);
+            fill(&mut self.system, &persona.system);
+            self.fill_params(&persona.params);
+            self.resize();
+        }
+    }
+
+    fn previous_persona(&mut self) {
+        if let Some(i) = self.selected {
+            self.select(i.saturating_sub(1));
+        } else if !self.personas.list.is_empty() {
+            self.select(self.personas.list.len() - 1);
+        }
+    }
+
+    fn next_persona(&mut self) {
+        let next = self.selected.map_or(0, |i| i + 1);
+        if next < self.personas.list.len() {
+            self.select(next);
+        }
+    }
+
+    // Saves the fields as a persona named after the title
+    fn save_persona(&mut self) {
+        if self.title.is_empty() {
+            self.message = Some("The title is used as the persona name".to_string());
+            return;
+        }
+
+        let params = match self.params() {
+            Ok(params) => params,
+            Err(err) => {
+                self.message = Some(err);
+                return;
+            }
+        };
+        let name = self.title.str().to_string();
+        // Seed messages can only be written in the file, keep them when editing
+        let seed = self
+            .personas
+            .list
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.seed.clone())
+            .unwrap_or_default();
+
+        let persona = Persona {
+            name: name.clone(),
+            system: self.system.str().to_string(),
+            params,
+            seed,
+        };
+        self.message = Some(match self.personas.upsert(persona) {
+            Ok(index) => {
+                self.selected = Some(index);
+                format!("Saved {}", name)
+            }
+            Err(err) => format!("Could not save: {}", err),
+        });
+    }
+
+    fn delete_persona(&mut self) {
+        if let Some(i) = self.selected {
+            self.message = Some(match self.personas.remove(i) {
+                Ok(_) => "Persona deleted".to_string(),
+                Err(err) => format!("Could not save: {}", err),
+            });
+            self.selected = None;
+        }
+    }
+
+    // Reads the file typed in the path prompt into the system field
+    fn load_system(&mut self) {
+        let path = match self.file.take() {
+            Some(mut file) => file.flush(),
+            None => return,
+        };
+        let path = path.trim();
+        // Expand the home directory like a shell would
+        let full = match (path.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => path.into(),
+        };
+
+        match fs::read_to_string(&full) {
+            Ok(text) => {
+                fill(&mut self.system, text.trim_end());
+                self.focus = Focus::System;
+                self.message = Some(format!("Loaded {}", path));
+                self.resize();
+            }
+            Err(err) => self.message = Some(format!("Could not read {}: {}", path, err)),
+        }
+    }
+
+    // First persona shown, the list scrolls just enough to show the selected one
+    fn list_offset(&self, rows: usize) -> usize {
+        self.selected.map_or(0, |i| (i + 1).saturating_sub(rows))
+    }
+
+    fn list_height(&self) -> u16 {
+        self.personas.list.len().clamp(1, 5) as u16 + 2
+    }
+
+    // The system field grows with its text, then scrolls
+    fn system_height(&self) -> u16 {
+        self.system.line_count().clamp(3, SYSTEM_ROWS) as u16 + 2
+    }
+
+    // Wraps the system field to the popup and grows it to fit
+    fn resize(&mut self) {
+        let width = popup(60, 0, self.size).width.saturating_sub(2);
+        self.system.update_size(width, SYSTEM_ROWS as u16);
+        let rows = self.system_height() - 2;
+        self.system.update_size(width, rows);
+    }
+
+    // Splits the popup into the persona list, title, system and model parameters
+    fn layout(&self, size: Rect) -> Vec<Rect> {
+        let area = popup(60, self.list_height() + self.system_height() + 6, size);
+        let mut layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(self.list_height()),
+                    Constraint::Length(3),
+                    Constraint::Length(self.system_height()),
+                    Constraint::Length(3),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+
+        let params = layout.pop().unwrap();
+        layout.append(
+            &mut Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    [
+                        Constraint::Percentage(25),
+                        Constraint::Percentage(35),
+                        Constraint::Percentage(20),
+                        Constraint::Percentage(20),
+                    ]
+                    .as_ref(),
+                )
+                .split(params),
+        );
+        layout
+    }
+
+    // Single line fields with their box, the system field is drawn on its own
+    fn fields(&self, layout: &[Rect]) -> [(Focus, &StaticPrompt, &'static str, Rect); 5] {
+        [
+            (
+                Focus::Title,
+                &self.title,
+                match self.editing {
+                    Some(_) => "Title",
+                    None => "New Chat",
+                },
+                layout[1],
+            ),
+            (Focus::Provider, &self.provider, "Provider", layout[3]),
+            (Focus::Model, &self.model, "Model", layout[4]),
+            (
+                Focus::Temperature,
+                &self.temperature,
+                "Temperature",
+                layout[5],
+            ),
+            (Focus::MaxTokens, &self.max_tokens, "Max tokens", layout[6]),
+        ]
+    }
 }
 
 impl Window for Creator {
     type InputReturn = Option<Action>;
 
+    fn update_size(&mut self, size: Rect) {
+        self.size = size;
+        self.resize();
+    }
+
     fn draw<B: Backend>(&self, f: &mut Frame<B>) {
-        let area = popup(60, 6, f.size());
-        let layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(3), Constraint::Min(3)].as_ref())
-            .split(area);
+        let layout = self.layout(f.size());
 
-        let (chat_style, system_style) = match self.focus {
-            Focus::Title => (Style::default(), Style::default().fg(Color::DarkGray)),
-            Focus::System => (Style::default().fg(Color::DarkGray), Style::default()),
+        let title = match &self.message {
+            Some(message) => format!("Personas - {}", message),
+            None => "Personas".to_string(),
+        };
+        let items: Vec<ListItem> = if self.personas.list.is_empty() {
+            let key = keymap()
+                .key(Context::Creator, keymap::Action::SavePersona)
+                .unwrap_or_default();
+            vec![ListItem::new(format!(
+                "No personas yet, {} saves the fields as one",
+                key
+            ))]
+        } else {
+            self.personas
+                .list
+                .iter()
+                .map(|p| ListItem::new(p.name.as_str()))
+                .collect()
         };
+        let mut state = ListState::default();
+        state.select(self.selected);
+        let personas = List::new(items)
+            .style(match self.focus {
+                Focus::Personas => Style::default(),
+                _ => theme().unfocused,
+            })
+            .highlight_style(theme().tab_highlight)
+            .block(Block::default().title(title).borders(Borders::ALL));
+        f.render_stateful_widget(personas, layout[0], &mut state);
 
-        let new_chat = Paragraph::new(self.title.str())
-            .style(chat_style)
-            .block(Block::default().title("New Chat").borders(Borders::ALL));
-        f.render_widget(new_chat, layout[0]);
+        // The path prompt covers the persona list while it is open
+        if let Some(file) = &self.file {
+            let area = Rect {
+                height: 3,
+                ..layout[0]
+            };
+            let path = Paragraph::new(file.str()).block(
+                Block::default()
+                    .title("Load system prompt from file")
+                    .borders(Borders::ALL),
+            );
+            f.render_widget(path, area);
+            f.set_cursor(area.x + file.cursor as u16 + 1, area.y + 1);
+        }
 
-        let system = Paragraph::new(self.system.str())
-            .style(system_style)
-            .block(Block::default().title("System").borders(Borders::ALL));
-        f.render_widget(system, layout[1]);
+        let focused = self.file.is_none() && self.focus == Focus::System;
+        let system = Paragraph::new(self.system.lines().join("\n"))
+            .style(match focused {
+                true => Style::default(),
+                false => theme().unfocused,
+            })
+            .block(
+                Block::default()
+                    .title(match self.editing {
+                        Some(true) => "System - the chat has one, empty removes it",
+                        Some(false) => "System - the chat has none",
+                        None => "System",
+                    })
+                    .borders(Borders::ALL),
+            );
+        f.render_widget(system, layout[2]);
+        if focused {
+            let (x, y) = self.system.cursor_position();
+            f.set_cursor(layout[2].x + x + 1, layout[2].y + y + 1);
+        }
 
-        let (selected, cursor) = match self.focus {
-            Focus::Title => (layout[0], self.title.cursor),
-            Focus::System => (layout[1], self.system.cursor),
-        };
+        for (focus, prompt, title, area) in self.fields(&layout) {
+            let style = match self.focus == focus {
+                true => Style::default(),
+                false => theme().unfocused,
+            };
+            let field = Paragraph::new(prompt.str())
+                .style(style)
+                .block(Block::default().title(title).borders(Borders::ALL));
+            f.render_widget(field, area);
 
-        f.set_cursor(
-            // Cursor goes past the
-            selected.x + cursor as u16 + 1,
-            // Move to where the text is
-            selected.y + 1,
-        )
+            if self.file.is_none() && self.focus == focus {
+                f.set_cursor(
+                    // Cursor goes past the
+                    area.x + prompt.cursor as u16 + 1,
+                    // Move to where the text is
+                    area.y + 1,
+                )
+            }
+        }
     }
 
-    fn input(&mut self, key: &KeyEvent, clipboard: &mut Clipboard) -> Self::InputReturn {
-        match key.code {
-            KeyCode::Esc => {
+    fn input(&mut self, key: &KeyEvent, clipboard: &mut dyn Clipboard) -> Self::InputReturn {
+        if let Some(file) = &mut self.file {
+            match keymap().action(Context::Creator, key) {
+                Some(keymap::Action::Submit) => self.load_system(),
+                Some(keymap::Action::Cancel) => self.file = None,
+                _ => file.input(key, clipboard),
+            }
+            return None;
+        }
+
+        let action = match keymap().action(Context::Creator, key) {
+            Some(keymap::Action::Cancel) => {
                 self.reset();
                 Some(Action::Quit)
             }
-            KeyCode::Tab => {
+            Some(keymap::Action::NextField) => {
                 self.next();
                 None
             }
-            KeyCode::Enter => {
-                if !self.title.is_empty() {
-                    Some(self.flush())
-                } else {
+            Some(keymap::Action::Submit) => {
+                if self.title.is_empty() && self.editing.is_some() {
+                    self.message = Some("The title can't be empty".to_string());
+                    None
+                } else if self.title.is_empty() {
+                    self.reset();
                     Some(Action::Quit)
+                } else {
+                    self.flush()
                 }
             }
+            Some(keymap::Action::SavePersona) => {
+                self.save_persona();
+                None
+            }
+            Some(keymap::Action::LoadSystem) => {
+                self.file = Some(StaticPrompt::new());
+                None
+            }
             _ => {
-                self.current_prompt().input(key, clipboard);
+                match self.current_prompt() {
+                    Some(prompt) => prompt.input(key, clipboard),
+                    None => match keymap().action(Context::Personas, key) {
+                        Some(keymap::Action::ScrollUp) => self.previous_persona(),
+                        Some(keymap::Action::ScrollDown) => self.next_persona(),
+                        Some(keymap::Action::DeletePersona) => self.delete_persona(),
+                        _ => {}
+                    },
+                }
                 None
             }
+        };
+        // The system field may have grown or shrunk
+        self.resize();
+        action
+    }
+
+    fn paste(&mut self, text: &str) -> Self::InputReturn {
+        match &mut self.file {
+            Some(file) => file.paste(text),
+            None => {
+                if let Some(prompt) = self.current_prompt() {
+                    prompt.checkpoint();
+                    prompt.paste(text);
+                }
+                self.resize();
+            }
+        }
+        None
+    }
+
+    fn mouse(&mut self, mouse: &MouseEvent) -> Self::InputReturn {
+        if self.file.is_some() {
+            return None;
+        }
+        if let MouseEventKind::Down(MouseButton::Left) = mouse.kind {
+            let layout = self.layout(self.size);
+            if contains(layout[0], mouse.column, mouse.row) {
+                self.focus = Focus::Personas;
+                // Skip the border, the list may be scrolled down
+                let row = (mouse.row - layout[0].y).saturating_sub(1) as usize;
+                let rows = layout[0].height.saturating_sub(2) as usize;
+                self.select(self.list_offset(rows) + row);
+            }
+            if contains(layout[2], mouse.column, mouse.row) {
+                self.focus = Focus::System;
+                // Skip the border
+                let row = (mouse.row - layout[2].y).saturating_sub(1) as usize;
+                let column = (mouse.column - layout[2].x).saturating_sub(1) as usize;
+                self.system.click(row, column);
+            }
+
+            let clicked = self
+                .fields(&layout)
+                .into_iter()
+                .find(|(_, _, _, area)| contains(*area, mouse.column, mouse.row))
+                .map(|(focus, _, _, area)| (focus, area));
+            if let Some((focus, area)) = clicked {
+                self.focus = focus;
+                // Skip the border
+                let column = (mouse.column - area.x).saturating_sub(1) as usize;
+                if let Some(prompt) = self.current_prompt() {
+                    prompt.move_to(column);
+                }
+            }
         }
+        None
     }
 }
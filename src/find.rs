@@ -0,0 +1,134 @@
+use crate::prompt::{Prompt, StaticPrompt};
+use crate::theme::theme;
+use tui::text::{Span, Spans};
+
+/// In-chat search bar state
+pub struct Find {
+    pub prompt: StaticPrompt,
+    // True while the query is being typed
+    pub typing: bool,
+    // Selected match as (wrapped line index, byte offset in that line)
+    pub current: Option<(usize, usize)>,
+}
+
+impl Find {
+    pub fn new() -> Self {
+        Self {
+            prompt: StaticPrompt::new(),
+            typing: true,
+            current: None,
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        self.prompt.str()
+    }
+
+    /// Picks the match after (or before) the current one, wrapping around the ends.
+    /// When nothing is selected yet it starts from the given line, which is usually
+    /// the bottom of the visible messages.
+    pub fn step(&mut self, matches: &[(usize, usize)], forward: bool, from: usize) {
+        self.current = match self.current {
+            Some(current) if forward => matches
+                .iter()
+                .find(|m| **m > current)
+                .or_else(|| matches.first()),
+            Some(current) => matches
+                .iter()
+                .rev()
+                .find(|m| **m < current)
+                .or_else(|| matches.last()),
+            None => matches
+                .iter()
+                .rev()
+                .find(|(line, _)| *line <= from)
+                .or_else(|| matches.last()),
+        }
+        .copied();
+    }
+
+    /// Splits the line into spans with every match highlighted
+    pub fn highlight<'a>(&self, line: &'a str, index: usize) -> Spans<'a> {
+        let query = self.query();
+        let mut spans = vec![];
+        let mut last = 0;
+
+        for start in find_matches(line, query) {
+            let end = start + query.len();
+            let style = if self.current == Some((index, start)) {
+                theme().find_current
+            } else {
+                theme().find_match
+            };
+
+            spans.push(Span::raw(&line[last..start]));
+            spans.push(Span::styled(&line[start..end], style));
+            last = end;
+        }
+        spans.push(Span::raw(&line[last..]));
+
+        Spans::from(spans)
+    }
+}
+
+/// Returns the byte offset of every case insensitive match of query in line
+pub fn find_matches(line: &str, query: &str) -> Vec<usize> {
+    if query.is_empty() {
+        return vec![];
+    }
+
+    // ASCII lowercasing keeps byte offsets intact
+    line.to_ascii_lowercase()
+        .match_indices(&query.to_ascii_lowercase())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::find::{find_matches, Find};
+    use crate::prompt::Prompt;
+
+    fn find(query: &str) -> Find {
+        let mut f = Find::new();
+        f.prompt.add_str(query);
+        f
+    }
+
+    #[test]
+    fn matches() {
+        assert_eq!(find_matches("Find the find", "find"), vec![0, 9]);
+        assert_eq!(find_matches("nothing", "find"), Vec::<usize>::new());
+        assert_eq!(find_matches("anything", ""), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn step() {
+        let matches = [(1, 0), (1, 4), (5, 2)];
+        let mut f = find("a");
+
+        // Starts from the closest match above the given line
+        f.step(&matches, true, 3);
+        assert_eq!(f.current, Some((1, 4)));
+
+        f.step(&matches, true, 3);
+        assert_eq!(f.current, Some((5, 2)));
+
+        // Wraps around
+        f.step(&matches, true, 3);
+        assert_eq!(f.current, Some((1, 0)));
+
+        f.step(&matches, false, 3);
+        assert_eq!(f.current, Some((5, 2)));
+    }
+
+    #[test]
+    fn highlight() {
+        let mut f = find("ab");
+        f.current = Some((0, 3));
+        let spans = f.highlight("ab ab", 0);
+        let text: Vec<&str> = spans.0.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(text, vec!["", "ab", " ", "ab", ""]);
+        assert_ne!(spans.0[1].style, spans.0[3].style);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::clipboard::Clipboard;
+use crate::commands::COMMANDS;
+use crate::keymap::{keymap, Context};
+use crate::theme::theme;
+use crate::{popup, Window};
+use crossterm::event::KeyEvent;
+use tui::{
+    backend::Backend,
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+/// Popup listing the keys of the contexts currently receiving input
+pub struct Help {
+    contexts: Vec<Context>,
+}
+
+impl Help {
+    pub fn new(contexts: Vec<Context>) -> Self {
+        Self { contexts }
+    }
+
+    fn lines(&self) -> Vec<Spans<'static>> {
+        let mut lines = vec![];
+        for context in self.contexts.iter() {
+            if !lines.is_empty() {
+                lines.push(Spans::from(""));
+            }
+            lines.push(Spans::from(Span::styled(context.name(), theme().heading)));
+            for (keys, description) in keymap().help(*context) {
+                lines.push(Spans::from(format!("  {:<20}{}", keys, description)));
+            }
+            // Slash commands are written in the chat prompt
+            if *context == Context::Writing {
+                lines.push(Spans::from(""));
+                lines.push(Spans::from(Span::styled("commands", theme().heading)));
+                for (name, args, description) in COMMANDS {
+                    let command = format!("/{} {}", name, args);
+                    lines.push(Spans::from(format!("  {:<20}{}", command, description)));
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl Window for Help {
+    type InputReturn = bool;
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let lines = self.lines();
+        // Keep the popup inside small screens, the rest can't be shown anyway
+        let height = (lines.len() as u16 + 2).min(f.size().height);
+        let area = popup(60, height, f.size());
+
+        let help = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Help - press any key to close"),
+        );
+        f.render_widget(Clear, area);
+        f.render_widget(help, area);
+    }
+
+    // Any key closes the help
+    fn input(&mut self, _key: &KeyEvent, _clipboard: &mut dyn Clipboard) -> Self::InputReturn {
+        true
+    }
+}
@@ -0,0 +1,174 @@
+use crate::config::{self, ConfigError};
+use crate::find::find_matches;
+use crate::prompt::{Prompt, StaticPrompt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+// Oldest prompts are forgotten past this
+const LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Default)]
+struct HistoryFile {
+    #[serde(default)]
+    prompts: Vec<String>,
+}
+
+/// Prompts sent from every chat, oldest first, stored in `history.toml` in the state directory
+pub struct History {
+    prompts: Vec<String>,
+    // None when there is no state directory, the history then lasts for the session
+    path: Option<PathBuf>,
+}
+
+impl History {
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = config::state_dir().map(|dir| dir.join("history.toml"));
+        let file = match &path {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+                toml::from_str(&text).map_err(ConfigError::Parse)?
+            }
+            _ => HistoryFile::default(),
+        };
+
+        Ok(Self {
+            prompts: file.prompts,
+            path,
+        })
+    }
+
+    /// A history that is never written to disk
+    #[cfg(test)]
+    pub fn in_memory(prompts: &[&str]) -> Self {
+        Self {
+            prompts: prompts.iter().map(|p| p.to_string()).collect(),
+            path: None,
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(path) = &self.path {
+            let file = HistoryFile {
+                prompts: self.prompts.clone(),
+            };
+            let text = toml::to_string(&file).map_err(|err| err.to_string())?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            fs::write(path, text).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Adds a sent prompt, sending one again only moves it to the end
+    pub fn add(&mut self, prompt: &str) -> Result<(), String> {
+        self.prompts.retain(|p| p != prompt);
+        self.prompts.push(prompt.to_string());
+        let extra = self.prompts.len().saturating_sub(LIMIT);
+        self.prompts.drain(..extra);
+        self.save()
+    }
+
+    pub fn prompts(&self) -> &[String] {
+        &self.prompts
+    }
+}
+
+/// Prompts to walk through with Up, newest first: the chat's own and then the
+/// ones sent from other chats
+pub fn recall_list<'a>(chat: &'a [String], global: &'a [String]) -> Vec<&'a str> {
+    let mut list: Vec<&str> = chat.iter().rev().map(String::as_str).collect();
+    for prompt in global.iter().rev() {
+        if !list.contains(&prompt.as_str()) {
+            list.push(prompt);
+        }
+    }
+    list
+}
+
+/// Reverse search popup over the prompt history
+pub struct Search {
+    pub prompt: StaticPrompt,
+    // Index in the matches, 0 is the newest
+    pub selected: usize,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            prompt: StaticPrompt::new(),
+            selected: 0,
+        }
+    }
+
+    /// Entries containing the query, in the order of the list
+    pub fn matches<'a>(&self, list: &[&'a str]) -> Vec<&'a str> {
+        let query = self.prompt.str();
+        list.iter()
+            .filter(|p| query.is_empty() || !find_matches(p, query).is_empty())
+            .copied()
+            .collect()
+    }
+
+    pub fn older(&mut self, matches: usize) {
+        if self.selected + 1 < matches {
+            self.selected += 1;
+        }
+    }
+
+    pub fn newer(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::{recall_list, History, Search, LIMIT};
+    use crate::prompt::Prompt;
+
+    #[test]
+    fn add() {
+        let mut history = History::in_memory(&["one", "two"]);
+        history.add("one").unwrap();
+        assert_eq!(history.prompts(), ["two", "one"]);
+
+        for i in 0..LIMIT {
+            history.add(&i.to_string()).unwrap();
+        }
+        assert_eq!(history.prompts().len(), LIMIT);
+        assert_eq!(history.prompts()[0], "0");
+    }
+
+    #[test]
+    fn recall() {
+        let chat = ["fix it".to_string(), "explain".to_string()];
+        let global = [
+            "hello".to_string(),
+            "fix it".to_string(),
+            "translate".to_string(),
+        ];
+        assert_eq!(
+            recall_list(&chat, &global),
+            ["explain", "fix it", "translate", "hello"]
+        );
+    }
+
+    #[test]
+    fn search() {
+        let list = ["Explain this", "fix the tests", "Explain that"];
+        let mut search = Search::new();
+        assert_eq!(search.matches(&list).len(), 3);
+
+        search.prompt.add_str("explain");
+        let matches = search.matches(&list);
+        assert_eq!(matches, ["Explain this", "Explain that"]);
+
+        search.older(matches.len());
+        search.older(matches.len());
+        assert_eq!(search.selected, 1);
+        search.newer();
+        search.newer();
+        assert_eq!(search.selected, 0);
+    }
+}
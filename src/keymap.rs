@@ -0,0 +1,572 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::OnceLock;
+
+static KEYMAP: OnceLock<Keymap> = OnceLock::new();
+
+/// Where a key is pressed, each window reads the actions from its own context
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Context {
+    // Browsing the chats
+    Chats,
+    // Writing a prompt in a chat
+    Writing,
+    // Typing in the find bar
+    Find,
+    // Searching the prompt history
+    History,
+    // Asked to run a command from the prompt
+    Confirm,
+    // New chat popup
+    Creator,
+    // Persona list in the new chat popup
+    Personas,
+    Settings,
+    // Text editing shared by every prompt
+    Prompt,
+}
+
+// Contexts that receive keys at the same time and so can't share keys
+const OVERLAPPING: &[(Context, Context)] = &[
+    (Context::Writing, Context::Prompt),
+    (Context::Find, Context::Prompt),
+    (Context::History, Context::Prompt),
+    (Context::Creator, Context::Prompt),
+    (Context::Creator, Context::Personas),
+];
+
+impl Context {
+    pub const ALL: [Context; 9] = [
+        Context::Chats,
+        Context::Writing,
+        Context::Find,
+        Context::History,
+        Context::Confirm,
+        Context::Creator,
+        Context::Personas,
+        Context::Settings,
+        Context::Prompt,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Context::Chats => "chats",
+            Context::Writing => "writing",
+            Context::Find => "find",
+            Context::History => "history",
+            Context::Confirm => "confirm",
+            Context::Creator => "creator",
+            Context::Personas => "personas",
+            Context::Settings => "settings",
+            Context::Prompt => "prompt",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Quit,
+    NewChat,
+    DeleteChat,
+    EditChat,
+    Copy,
+    NextTab,
+    PreviousTab,
+    Write,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    ScrollTop,
+    ScrollBottom,
+    PreviousMessage,
+    NextMessage,
+    Find,
+    NextMatch,
+    PreviousMatch,
+    Back,
+    Help,
+    Submit,
+    Cancel,
+    NextField,
+    SavePersona,
+    DeletePersona,
+    LoadSystem,
+    SearchHistory,
+    EditPrompt,
+    EditSystem,
+    EditMessage,
+    Complete,
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    Backspace,
+    Delete,
+    StartOfLine,
+    EndOfLine,
+    KillToEnd,
+    KillToStart,
+    KillWord,
+    KillWordBack,
+    Yank,
+    YankPop,
+    Undo,
+    Redo,
+    Newline,
+    Paste,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NewChat => "new_chat",
+            Action::DeleteChat => "delete_chat",
+            Action::EditChat => "edit_chat",
+            Action::Copy => "copy",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::Write => "write",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollDown => "scroll_down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::ScrollTop => "scroll_top",
+            Action::ScrollBottom => "scroll_bottom",
+            Action::PreviousMessage => "previous_message",
+            Action::NextMessage => "next_message",
+            Action::Find => "find",
+            Action::NextMatch => "next_match",
+            Action::PreviousMatch => "previous_match",
+            Action::Back => "back",
+            Action::Help => "help",
+            Action::Submit => "submit",
+            Action::Cancel => "cancel",
+            Action::NextField => "next_field",
+            Action::SavePersona => "save_persona",
+            Action::DeletePersona => "delete_persona",
+            Action::LoadSystem => "load_system",
+            Action::SearchHistory => "search_history",
+            Action::EditPrompt => "edit_prompt",
+            Action::EditSystem => "edit_system",
+            Action::EditMessage => "edit_message",
+            Action::Complete => "complete",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::WordLeft => "word_left",
+            Action::WordRight => "word_right",
+            Action::LineStart => "line_start",
+            Action::LineEnd => "line_end",
+            Action::Backspace => "backspace",
+            Action::Delete => "delete",
+            Action::StartOfLine => "start_of_line",
+            Action::EndOfLine => "end_of_line",
+            Action::KillToEnd => "kill_to_end",
+            Action::KillToStart => "kill_to_start",
+            Action::KillWord => "kill_word",
+            Action::KillWordBack => "kill_word_back",
+            Action::Yank => "yank",
+            Action::YankPop => "yank_pop",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Newline => "newline",
+            Action::Paste => "paste",
+        }
+    }
+}
+
+// Every available action with its default keys and help text
+#[rustfmt::skip]
+const DEFAULTS: &[(Context, Action, &[&str], &str)] = &[
+    (Context::Chats, Action::Quit, &["q"], "Quit"),
+    (Context::Chats, Action::NewChat, &["a"], "Create a new chat"),
+    (Context::Chats, Action::DeleteChat, &["d"], "Delete the current chat"),
+    (Context::Chats, Action::EditChat, &["e"], "Edit the title, system prompt and model of the chat"),
+    (Context::Chats, Action::Copy, &["y"], "Copy the last answer"),
+    (Context::Chats, Action::EditSystem, &["s"], "Edit the system prompt in $EDITOR"),
+    (Context::Chats, Action::EditMessage, &["m"], "Edit the message at the top in $EDITOR"),
+    (Context::Chats, Action::NextTab, &["right"], "Next chat"),
+    (Context::Chats, Action::PreviousTab, &["left"], "Previous chat"),
+    (Context::Chats, Action::Write, &["enter"], "Start writing a prompt"),
+    (Context::Chats, Action::ScrollUp, &["up"], "Scroll up"),
+    (Context::Chats, Action::ScrollDown, &["down"], "Scroll down"),
+    (Context::Chats, Action::PageUp, &["pageup"], "Scroll up a page"),
+    (Context::Chats, Action::PageDown, &["pagedown"], "Scroll down a page"),
+    (Context::Chats, Action::ScrollTop, &["home"], "Go to the first message"),
+    (Context::Chats, Action::ScrollBottom, &["end"], "Go to the last message"),
+    (Context::Chats, Action::PreviousMessage, &["["], "Go to the previous message"),
+    (Context::Chats, Action::NextMessage, &["]"], "Go to the next message"),
+    (Context::Chats, Action::Find, &["/"], "Find in the chat"),
+    (Context::Chats, Action::NextMatch, &["n"], "Next match"),
+    (Context::Chats, Action::PreviousMatch, &["N"], "Previous match"),
+    (Context::Chats, Action::Back, &["esc"], "Close the find bar or open settings"),
+    (Context::Chats, Action::Help, &["?", "f1"], "Show the key bindings"),
+    (Context::Writing, Action::Submit, &["enter"], "Send the prompt"),
+    (Context::Writing, Action::Cancel, &["esc"], "Stop writing"),
+    (Context::Writing, Action::SearchHistory, &["ctrl+r"], "Search the sent prompts"),
+    (Context::Writing, Action::EditPrompt, &["ctrl+x"], "Write the prompt in $EDITOR"),
+    (Context::Writing, Action::Complete, &["tab"], "Complete a @path or /command"),
+    (Context::Writing, Action::Help, &["f1"], "Show the key bindings"),
+    (Context::Find, Action::Submit, &["enter"], "Confirm the search"),
+    (Context::Find, Action::Cancel, &["esc"], "Close the find bar"),
+    (Context::Find, Action::Help, &["f1"], "Show the key bindings"),
+    (Context::History, Action::Submit, &["enter"], "Use the selected prompt"),
+    (Context::History, Action::Cancel, &["esc"], "Close the search"),
+    (Context::History, Action::ScrollDown, &["ctrl+r"], "Older match"),
+    (Context::History, Action::ScrollUp, &["ctrl+s"], "Newer match"),
+    (Context::History, Action::Help, &["f1"], "Show the key bindings"),
+    (Context::Confirm, Action::Submit, &["enter", "y"], "Run the command or call"),
+    (Context::Confirm, Action::Cancel, &["esc", "n"], "Don't run it"),
+    (Context::Creator, Action::Submit, &["enter"], "Create or update the chat"),
+    (Context::Creator, Action::Cancel, &["esc"], "Close without saving"),
+    (Context::Creator, Action::NextField, &["tab"], "Switch field"),
+    (Context::Creator, Action::SavePersona, &["ctrl+s"], "Save the fields as a persona"),
+    (Context::Creator, Action::LoadSystem, &["ctrl+o"], "Load the system prompt from a file"),
+    (Context::Creator, Action::Help, &["f1"], "Show the key bindings"),
+    (Context::Personas, Action::ScrollUp, &["up"], "Previous persona"),
+    (Context::Personas, Action::ScrollDown, &["down"], "Next persona"),
+    (Context::Personas, Action::DeletePersona, &["delete"], "Delete the persona"),
+    (Context::Settings, Action::Cancel, &["esc"], "Back to the chats"),
+    (Context::Settings, Action::Help, &["?", "f1"], "Show the key bindings"),
+    (Context::Prompt, Action::Left, &["left", "ctrl+b"], "Move left"),
+    (Context::Prompt, Action::Right, &["right", "ctrl+f"], "Move right"),
+    (Context::Prompt, Action::WordLeft, &["ctrl+left", "alt+b"], "Move a word left"),
+    (Context::Prompt, Action::WordRight, &["ctrl+right", "alt+f"], "Move a word right"),
+    // Multi-line fields move between rows instead
+    (Context::Prompt, Action::LineStart, &["down"], "Go to the start, or a row down"),
+    (Context::Prompt, Action::LineEnd, &["up"], "Go to the end, or a row up"),
+    (Context::Prompt, Action::StartOfLine, &["home", "ctrl+a"], "Go to the start of the line"),
+    (Context::Prompt, Action::EndOfLine, &["end", "ctrl+e"], "Go to the end of the line"),
+    (Context::Prompt, Action::Backspace, &["backspace", "ctrl+h"], "Delete the previous character"),
+    (Context::Prompt, Action::Delete, &["delete", "ctrl+d"], "Delete the character under the cursor"),
+    (Context::Prompt, Action::KillToEnd, &["ctrl+k"], "Cut to the end of the line"),
+    (Context::Prompt, Action::KillToStart, &["ctrl+u"], "Cut to the start of the line"),
+    (Context::Prompt, Action::KillWord, &["alt+d"], "Cut the next word"),
+    (Context::Prompt, Action::KillWordBack, &["ctrl+w"], "Cut the previous word"),
+    (Context::Prompt, Action::Yank, &["ctrl+y"], "Paste the last cut text"),
+    (Context::Prompt, Action::YankPop, &["alt+y"], "Swap the pasted text for an older cut"),
+    // Terminals send ctrl+_ as 0x1F, which crossterm reports as ctrl+7
+    (Context::Prompt, Action::Undo, &["ctrl+z", "ctrl+7"], "Undo the last edit"),
+    (Context::Prompt, Action::Redo, &["alt+z"], "Redo the undone edit"),
+    (Context::Prompt, Action::Newline, &["alt+enter"], "Start a new line in multi-line fields"),
+    (Context::Prompt, Action::Paste, &["ctrl+v"], "Paste from the clipboard"),
+];
+
+/// A key press with its modifiers
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already part of the character and of BackTab
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parses keys written like `ctrl+v`, `pageup` or `N`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = s.split('+').collect();
+        // Allow binding the plus key itself
+        if s.ends_with('+') {
+            parts.pop();
+            parts.pop();
+            parts.push("+");
+        }
+
+        let (key, modifier_names) = parts.split_last().ok_or(format!("empty key {:?}", s))?;
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names {
+            modifiers |= match name.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier {:?} in {:?}", name, s)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key {:?}", s)),
+                },
+            },
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl From<&KeyEvent> for Key {
+    fn from(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+struct Binding {
+    context: Context,
+    action: Action,
+    keys: Vec<Key>,
+    description: &'static str,
+}
+
+/// Maps key presses to actions for every context
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    lookup: HashMap<(Context, Key), Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&HashMap::new()).unwrap()
+    }
+}
+
+impl Keymap {
+    /// Builds the default keymap with the user overrides applied on top,
+    /// an overridden action loses all of its default keys
+    pub fn new(overrides: &HashMap<String, HashMap<String, Vec<String>>>) -> Result<Self, String> {
+        let mut bindings = vec![];
+        for (context, action, keys, description) in DEFAULTS {
+            let keys = keys
+                .iter()
+                .map(|k| Key::parse(k))
+                .collect::<Result<_, _>>()?;
+            bindings.push(Binding {
+                context: *context,
+                action: *action,
+                keys,
+                description,
+            });
+        }
+
+        for (context_name, actions) in overrides {
+            let context = Context::ALL
+                .into_iter()
+                .find(|c| c.name() == context_name)
+                .ok_or(format!("unknown context {:?}", context_name))?;
+
+            for (action_name, keys) in actions {
+                let binding = bindings
+                    .iter_mut()
+                    .find(|b| b.context == context && b.action.name() == action_name)
+                    .ok_or(format!("unknown action {}.{}", context_name, action_name))?;
+                binding.keys = keys
+                    .iter()
+                    .map(|k| Key::parse(k))
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+
+        let mut lookup = HashMap::new();
+        for binding in bindings.iter() {
+            for key in binding.keys.iter() {
+                if let Some(other) = lookup.insert((binding.context, *key), binding.action) {
+                    return Err(conflict(
+                        key,
+                        (binding.context, other),
+                        (binding.context, binding.action),
+                    ));
+                }
+            }
+        }
+
+        for (a, b) in OVERLAPPING {
+            for binding in bindings.iter().filter(|binding| binding.context == *b) {
+                for key in binding.keys.iter() {
+                    if let Some(other) = lookup.get(&(*a, *key)) {
+                        return Err(conflict(key, (*a, *other), (*b, binding.action)));
+                    }
+                }
+            }
+        }
+
+        Ok(Self { bindings, lookup })
+    }
+
+    /// Returns the action bound to the key press in the context
+    pub fn action(&self, context: Context, key: &KeyEvent) -> Option<Action> {
+        self.lookup.get(&(context, Key::from(key))).copied()
+    }
+
+    /// Returns the first key bound to the action, used for short hints
+    pub fn key(&self, context: Context, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.context == context && binding.action == action)
+            .and_then(|binding| binding.keys.first())
+            .map(|key| key.to_string())
+    }
+
+    /// Lists the keys and descriptions of every action in the context
+    pub fn help(&self, context: Context) -> Vec<(String, &'static str)> {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.context == context && !binding.keys.is_empty())
+            .map(|binding| {
+                let keys: Vec<String> = binding.keys.iter().map(|k| k.to_string()).collect();
+                (keys.join(", "), binding.description)
+            })
+            .collect()
+    }
+}
+
+fn conflict(key: &Key, a: (Context, Action), b: (Context, Action)) -> String {
+    format!(
+        "{} is bound to both {}.{} and {}.{}",
+        key,
+        a.0.name(),
+        a.1.name(),
+        b.0.name(),
+        b.1.name()
+    )
+}
+
+/// Sets the keymap used by every window, can only be done once
+pub fn init(keymap: Keymap) {
+    let _ = KEYMAP.set(keymap);
+}
+
+pub fn keymap() -> &'static Keymap {
+    KEYMAP.get_or_init(Keymap::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keymap::{Action, Context, Key, Keymap};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use std::collections::HashMap;
+
+    fn overrides(
+        context: &str,
+        action: &str,
+        keys: &[&str],
+    ) -> HashMap<String, HashMap<String, Vec<String>>> {
+        let keys = keys.iter().map(|k| k.to_string()).collect();
+        HashMap::from([(
+            context.to_string(),
+            HashMap::from([(action.to_string(), keys)]),
+        )])
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Key::parse("ctrl+v").unwrap(),
+            Key::new(KeyCode::Char('v'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Key::parse("shift+n").unwrap(),
+            Key::new(KeyCode::Char('N'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            Key::parse("ctrl++").unwrap(),
+            Key::new(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(Key::parse("PageUp").unwrap().code, KeyCode::PageUp);
+        assert_eq!(Key::parse("f5").unwrap().code, KeyCode::F(5));
+        assert!(Key::parse("hyper+x").is_err());
+        assert!(Key::parse("nokey").is_err());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Key::parse("ctrl+left").unwrap().to_string(), "Ctrl+Left");
+        assert_eq!(Key::parse("space").unwrap().to_string(), "Space");
+    }
+
+    #[test]
+    fn action() {
+        let keymap = Keymap::default();
+        let shift_n = KeyEvent::new(KeyCode::Char('N'), KeyModifiers::SHIFT);
+        assert_eq!(
+            keymap.action(Context::Chats, &shift_n),
+            Some(Action::PreviousMatch)
+        );
+        let q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(Context::Writing, &q), None);
+    }
+
+    #[test]
+    fn override_keys() {
+        let keymap = Keymap::new(&overrides("chats", "quit", &["ctrl+q"])).unwrap();
+        let q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        let ctrl_q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.action(Context::Chats, &q), None);
+        assert_eq!(keymap.action(Context::Chats, &ctrl_q), Some(Action::Quit));
+
+        assert!(Keymap::new(&overrides("chats", "fly", &["f"])).is_err());
+        assert!(Keymap::new(&overrides("space", "quit", &["q"])).is_err());
+    }
+
+    #[test]
+    fn conflicts() {
+        // Same context
+        assert!(Keymap::new(&overrides("chats", "quit", &["d"])).is_err());
+        // Contexts that are active together
+        assert!(Keymap::new(&overrides("writing", "submit", &["ctrl+v"])).is_err());
+        // Contexts that are never active together
+        assert!(Keymap::new(&overrides("settings", "cancel", &["q"])).is_ok());
+    }
+
+    #[test]
+    fn help() {
+        let keymap = Keymap::new(&overrides("chats", "quit", &["q", "ctrl+c"])).unwrap();
+        let help = keymap.help(Context::Chats);
+        assert_eq!(help[0], ("q, Ctrl+c".to_string(), "Quit"));
+        assert!(help.iter().all(|(keys, _)| !keys.is_empty()));
+    }
+}
@@ -1,21 +1,41 @@
+mod attach;
 mod chat;
+mod clipboard;
+mod commands;
+mod config;
 mod creator;
+mod find;
+mod help;
+mod history;
+mod keymap;
+mod persona;
 mod prompt;
+mod provider;
 mod settings;
+mod shell;
+mod terminal;
+mod theme;
+mod tools;
 
 use crate::{
     chat::Chats,
+    clipboard::Clipboard,
+    config::Config,
     creator::{Action, Creator},
+    help::Help,
+    history::History,
+    keymap::{keymap, Context, Keymap},
+    persona::Personas,
+    provider::Providers,
     settings::Settings,
+    terminal::TerminalGuard,
+    theme::Theme,
 };
-use arboard::Clipboard;
 use crossterm::event::poll;
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
+use crossterm::event::{self, Event, KeyEvent, MouseEvent};
 use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use tui::{
     backend::{Backend, CrosstermBackend},
@@ -25,7 +45,7 @@ use tui::{
 
 // TODO: improve naming on many of these
 pub trait Window {
-    type InputReturn;
+    type InputReturn: Default;
 
     // Updates the screen
     fn update<B: Backend>(&mut self, f: &mut Frame<B>) {
@@ -33,11 +53,19 @@ pub trait Window {
         self.draw(f);
     }
     // Handle screen updates, useful when caching
-    fn update_size(&mut self, size: Rect) {}
+    fn update_size(&mut self, _size: Rect) {}
     // Handle the screen writing
-    fn draw<B: Backend>(&self, f: &mut Frame<B>) {}
+    fn draw<B: Backend>(&self, _f: &mut Frame<B>) {}
     // Handle the input handling and processing
-    fn input(&mut self, key: &KeyEvent, clipboard: &mut Clipboard) -> Self::InputReturn;
+    fn input(&mut self, key: &KeyEvent, clipboard: &mut dyn Clipboard) -> Self::InputReturn;
+    // Handle mouse clicks and scrolling, ignored unless the window needs it
+    fn mouse(&mut self, _mouse: &MouseEvent) -> Self::InputReturn {
+        Self::InputReturn::default()
+    }
+    // Handle text pasted into the terminal, it arrives whole instead of as keys
+    fn paste(&mut self, _text: &str) -> Self::InputReturn {
+        Self::InputReturn::default()
+    }
 }
 
 struct App {
@@ -45,6 +73,8 @@ struct App {
     chats: Chats,
     creator: Creator,
     settings: Settings,
+    // Some while the help popup is open
+    help: Option<Help>,
 }
 
 enum ViewState {
@@ -57,28 +87,51 @@ enum ViewState {
 }
 
 impl App {
-    fn new() -> Self {
+    fn new(personas: Personas, providers: Providers, history: History) -> Self {
         Self {
             view_state: ViewState::Chats,
-            chats: Chats::new(),
-            creator: Creator::new(),
+            creator: Creator::new(personas, providers.names()),
+            chats: Chats::new(providers, history),
             settings: Settings {},
+            help: None,
+        }
+    }
+
+    // Contexts receiving keys in the current view
+    fn contexts(&self) -> Vec<Context> {
+        match &self.view_state {
+            ViewState::Chats => self.chats.contexts(),
+            ViewState::Settings => vec![Context::Settings],
+            ViewState::NewChat => self.creator.contexts(),
         }
     }
 }
 
 impl App {
-    fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
-        let mut clipboard = Clipboard::new().unwrap();
-
-        loop {
-            terminal.draw(|f| self.update(f)).unwrap();
+    // Runs until the user quits or stop is set by a signal
+    fn run<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        clipboard: &mut dyn Clipboard,
+        stop: &AtomicBool,
+    ) -> io::Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            terminal.draw(|f| self.update(f))?;
             // TODO setting for this
             if poll(Duration::from_millis(100))? {
-                if let Event::Key(key) = event::read()? {
-                    if self.input(&key, &mut clipboard) {
-                        break;
-                    }
+                let quit = match event::read()? {
+                    Event::Key(key) => self.input(&key, clipboard),
+                    Event::Mouse(mouse) => self.mouse(&mouse),
+                    Event::Paste(text) => self.paste(&text),
+                    _ => false,
+                };
+                if quit {
+                    break;
+                }
+                if let Some((edit, text)) = self.chats.take_edit() {
+                    let edited = terminal::edit(&text);
+                    terminal.clear()?;
+                    self.chats.finish_edit(edit, edited);
                 }
             }
         }
@@ -87,6 +140,37 @@ impl App {
     }
 }
 
+impl App {
+    // Returns an option enum with an action,
+    // None means do nothing, Creator::Quit means go back,
+    // Creator::New() means add a new chat and Creator::Update() edits the current one
+    fn creator_action(&mut self, action: Option<Action>) {
+        if let Some(action) = action {
+            match action {
+                Action::Quit => self.view_state = ViewState::Chats,
+                Action::New {
+                    title,
+                    system,
+                    params,
+                    seed,
+                } => {
+                    let system = if system.is_empty() {
+                        None
+                    } else {
+                        Some(system)
+                    };
+                    self.chats.add_chat(title, system, params, seed);
+                    self.view_state = ViewState::Chats;
+                }
+                Action::Update(settings) => {
+                    self.chats.edit_chat(settings);
+                    self.view_state = ViewState::Chats;
+                }
+            }
+        }
+    }
+}
+
 impl Window for App {
     type InputReturn = bool;
 
@@ -96,22 +180,51 @@ impl Window for App {
             ViewState::Settings => self.settings.update(f),
             ViewState::NewChat => self.creator.update(f),
         }
+
+        if let Some(help) = self.help.as_mut() {
+            help.update(f);
+        }
     }
 
-    fn input(&mut self, key: &KeyEvent, clipboard: &mut Clipboard) -> Self::InputReturn {
+    fn input(&mut self, key: &KeyEvent, clipboard: &mut dyn Clipboard) -> Self::InputReturn {
+        if let Some(help) = self.help.as_mut() {
+            if help.input(key, clipboard) {
+                self.help = None;
+            }
+            return false;
+        }
+
+        let contexts = self.contexts();
+        if keymap().action(contexts[0], key) == Some(keymap::Action::Help) {
+            self.help = Some(Help::new(contexts));
+            return false;
+        }
+
         // Only input routing is done here
         match &self.view_state {
             ViewState::Chats => {
-                if key.code == KeyCode::Char('a') && !self.chats.writing {
+                // Keys go to the chats while typing or asked to confirm something
+                let action = match self.chats.typing() || contexts[0] == Context::Confirm {
+                    true => None,
+                    false => keymap().action(Context::Chats, key),
+                };
+                if action == Some(keymap::Action::NewChat) {
+                    self.view_state = ViewState::NewChat
+                }
+                if action == Some(keymap::Action::EditChat) {
+                    self.creator.edit(self.chats.settings());
                     self.view_state = ViewState::NewChat
                 }
-                if key.code == KeyCode::Esc && !self.chats.writing {
+                if action == Some(keymap::Action::Back) && !self.chats.finding() {
                     self.view_state = ViewState::Settings
                 } else {
                     // Simply returns a bool
                     if self.chats.input(key, clipboard) {
                         return true;
                     }
+                    if self.chats.take_help() {
+                        self.help = Some(Help::new(self.contexts()));
+                    }
                 }
             }
             ViewState::Settings => {
@@ -121,27 +234,42 @@ impl Window for App {
                 }
             }
             ViewState::NewChat => {
-                // Returns an option enum with an action,
-                // None means do nothing, Creator::Quit means go back and
-                // Creator::New() means add a new chat
-                if let Some(action) = self.creator.input(key, clipboard) {
-                    match action {
-                        Action::Quit => self.view_state = ViewState::Chats,
-                        Action::New { title, system } => {
-                            let system = if system.is_empty() {
-                                None
-                            } else {
-                                Some(system)
-                            };
-                            self.chats.add_chat(title, system);
-                            self.view_state = ViewState::Chats;
-                        }
-                    }
-                }
+                let action = self.creator.input(key, clipboard);
+                self.creator_action(action);
+            }
+        }
+
+        false
+    }
+
+    fn paste(&mut self, text: &str) -> Self::InputReturn {
+        if self.help.is_some() {
+            return false;
+        }
+        match &self.view_state {
+            ViewState::Chats => self.chats.paste(text),
+            ViewState::Settings => self.settings.paste(text),
+            ViewState::NewChat => {
+                let action = self.creator.paste(text);
+                self.creator_action(action);
+                false
+            }
+        }
+    }
+
+    fn mouse(&mut self, mouse: &MouseEvent) -> Self::InputReturn {
+        match &self.view_state {
+            ViewState::Chats => return self.chats.mouse(mouse),
+            ViewState::Settings => {
+                self.settings.mouse(mouse);
+            }
+            ViewState::NewChat => {
+                let action = self.creator.mouse(mouse);
+                self.creator_action(action);
             }
         }
 
-        return false;
+        false
     }
 }
 
@@ -166,7 +294,8 @@ pub fn popup(percent_x: u16, height: u16, r: Rect) -> Rect {
         )
         .split(r);
 
-    // Avoid weird layout issues
+    // Avoid weird layout issues, popups taller than the screen are cut
+    let height = height.min(r.height);
     let total_height = r.height - height;
     let half_height = total_height / 2;
 
@@ -183,26 +312,330 @@ pub fn popup(percent_x: u16, height: u16, r: Rect) -> Rect {
         .split(layout[1])[1]
 }
 
+/// Checks if a terminal cell is inside the area
+pub fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x && column < area.right() && row >= area.y && row < area.bottom()
+}
+
+// Prints every key binding, grouped by context
+fn print_keys() {
+    for context in Context::ALL {
+        println!("[{}]", context.name());
+        for (keys, description) in keymap().help(context) {
+            println!("  {:<24}{}", keys, description);
+        }
+        println!();
+    }
+}
+
 //#[tokio::main]
 fn main() -> Result<(), io::Error> {
-    // setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    let mut app = App::new();
-    app.run(&mut terminal).unwrap();
-
-    // restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    Ok(())
+    // Config errors are reported before the terminal is taken over
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("gpterm: {}", err);
+            std::process::exit(1);
+        }
+    };
+    match Keymap::new(&config.keys) {
+        Ok(keys) => keymap::init(keys),
+        Err(err) => {
+            eprintln!("gpterm: {}", config::ConfigError::Keymap(err));
+            std::process::exit(1);
+        }
+    }
+    match Theme::load(config.theme.as_deref().unwrap_or("dark")) {
+        // https://no-color.org
+        Ok(theme) if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => {
+            theme::init(theme.no_color())
+        }
+        Ok(theme) => theme::init(theme),
+        Err(err) => {
+            eprintln!("gpterm: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let providers = match Providers::new(&config.providers, config.provider.as_deref()) {
+        Ok(providers) => providers,
+        Err(err) => {
+            eprintln!("gpterm: {}", config::ConfigError::Provider(err));
+            std::process::exit(1);
+        }
+    };
+
+    let personas = match Personas::load() {
+        Ok(personas) => personas,
+        Err(err) => {
+            eprintln!("gpterm: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let history = match History::load() {
+        Ok(history) => history,
+        Err(err) => {
+            eprintln!("gpterm: history: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if std::env::args().any(|arg| arg == "--keys") {
+        print_keys();
+        return Ok(());
+    }
+
+    // setup terminal, the guard and the hook put it back however the app ends
+    let stop = terminal::signals()?;
+    terminal::install_panic_hook();
+    let guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let mut clipboard = clipboard::select(config.clipboard);
+    let mut app = App::new(personas, providers, history);
+    app.chats.set_vim(config.vim);
+    app.chats.set_tools(config.tools);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        app.run(&mut terminal, clipboard.as_mut(), &stop)
+    }));
+    drop(guard);
+
+    // Chats only live in memory, keep them when the app didn't choose to end
+    let interrupted = stop.load(Ordering::Relaxed);
+    if result.is_err() || interrupted {
+        save_chats(&app.chats);
+    }
+    match result {
+        Ok(result) => result,
+        Err(_) => std::process::exit(101),
+    }
+}
+
+fn save_chats(chats: &Chats) {
+    if let Some(transcript) = chats.transcript() {
+        match terminal::save("chats", "md", &transcript) {
+            Ok(path) => eprintln!("gpterm: chats saved to {}", path.display()),
+            Err(err) => eprintln!("gpterm: could not save the chats: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::ModelParams;
+    use crate::clipboard::Memory;
+    use crate::config::ProviderConfig;
+    use crate::help::Help;
+    use crate::history::History;
+    use crate::keymap::Context;
+    use crate::persona::{Persona, Personas, Seed};
+    use crate::provider::mock::{MockServer, Reply};
+    use crate::provider::Providers;
+    use crate::{App, ViewState, Window};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+    use tui::backend::TestBackend;
+    use tui::buffer::Buffer;
+    use tui::Terminal;
+
+    // Normal, narrow and tiny terminals, the tiny ones only have to not panic
+    const SIZES: [(u16, u16); 5] = [(80, 24), (40, 12), (16, 8), (4, 2), (1, 1)];
+
+    fn app() -> App {
+        let mut configs = BTreeMap::new();
+        configs.insert(
+            "local".to_string(),
+            ProviderConfig::Local {
+                base_url: "http://localhost:8080/v1".to_string(),
+                api_key: None,
+                model: None,
+            },
+        );
+        let personas = Personas::in_memory(vec![Persona {
+            name: "Reviewer".to_string(),
+            system: "You review code".to_string(),
+            params: ModelParams::default(),
+            seed: vec![],
+        }]);
+        App::new(
+            personas,
+            Providers::new(&configs, None).unwrap(),
+            History::in_memory(&[]),
+        )
+    }
+
+    // An app with a short conversation in a second tab
+    fn conversation() -> App {
+        let mut app = app();
+        let seed = |role: &str, content: &str| Seed {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        app.chats.add_chat(
+            "Colors".to_string(),
+            Some("Be brief".to_string()),
+            ModelParams::default(),
+            vec![
+                seed("user", "Why is the sky blue?"),
+                seed(
+                    "assistant",
+                    "Sunlight scatters off the air, and blue light scatters the most \
+                     because of its short wavelength.",
+                ),
+                seed("user", "And sunsets?"),
+            ],
+        );
+        app
+    }
+
+    // Cell symbols, one line per row
+    fn text(buffer: &Buffer) -> String {
+        let mut text = String::new();
+        for y in 0..buffer.area.height {
+            for x in 0..buffer.area.width {
+                text += &buffer.get(x, y).symbol;
+            }
+            text = text.trim_end().to_string() + "\n";
+        }
+        text
+    }
+
+    // Draws the app at every size and compares the screens with `src/snapshots/{name}.txt`.
+    // Run with UPDATE_SNAPSHOTS=1 to write the snapshots instead.
+    fn snapshot(name: &str, mut app: App) {
+        let mut screens = String::new();
+        for (width, height) in SIZES {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            terminal.draw(|f| app.update(f)).unwrap();
+            screens += &format!("=== {}x{}\n", width, height);
+            screens += &text(terminal.backend().buffer());
+        }
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/snapshots")
+            .join(format!("{}.txt", name));
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, screens).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("no snapshot at {:?}, run with UPDATE_SNAPSHOTS=1", path));
+        assert!(
+            screens == expected,
+            "{} changed, run with UPDATE_SNAPSHOTS=1 if it should have\n\
+             --- expected\n{}\n--- drawn\n{}",
+            name,
+            expected,
+            screens
+        );
+    }
+
+    #[test]
+    fn chats() {
+        snapshot("chats", app());
+        snapshot("conversation", conversation());
+    }
+
+    #[test]
+    fn settings() {
+        let mut app = app();
+        app.view_state = ViewState::Settings;
+        snapshot("settings", app);
+    }
+
+    #[test]
+    fn creator() {
+        let mut app = app();
+        app.view_state = ViewState::NewChat;
+        snapshot("new_chat", app);
+
+        let mut app = conversation();
+        app.creator.edit(app.chats.settings());
+        app.view_state = ViewState::NewChat;
+        snapshot("edit_chat", app);
+    }
+
+    #[test]
+    fn confirm() {
+        let mut app = conversation();
+        app.paste("!cargo test 2>&1");
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        app.input(&enter, &mut Memory::default());
+        snapshot("confirm", app);
+    }
+
+    #[test]
+    fn commands() {
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        let mut app = conversation();
+        app.paste("/exp");
+        snapshot("command_hint", app);
+
+        let mut app = conversation();
+        app.paste("/help");
+        app.input(&enter, &mut Memory::default());
+        snapshot("help_commands", app);
+    }
+
+    #[test]
+    fn decline_call() {
+        let server = MockServer::start(vec![Reply::call("shell", &[r#"{"command":"ls"}"#])]);
+        let mut configs = BTreeMap::new();
+        configs.insert(
+            "mock".to_string(),
+            ProviderConfig::Local {
+                base_url: server.url(),
+                api_key: None,
+                model: None,
+            },
+        );
+        let mut app = App::new(
+            Personas::in_memory(vec![]),
+            Providers::new(&configs, None).unwrap(),
+            History::in_memory(&[]),
+        );
+        app.chats.set_tools(true);
+        let press = |app: &mut App, code| {
+            app.input(
+                &KeyEvent::new(code, KeyModifiers::NONE),
+                &mut Memory::default(),
+            );
+        };
+        app.paste("Look around");
+        press(&mut app, KeyCode::Enter);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        for _ in 0..500 {
+            if app.contexts()[0] == Context::Confirm {
+                break;
+            }
+            terminal.draw(|f| app.update(f)).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(app.contexts(), [Context::Confirm]);
+
+        // The chats keys wait until the call is answered, esc declines it
+        press(&mut app, KeyCode::Char('a'));
+        assert!(matches!(app.view_state, ViewState::Chats));
+        press(&mut app, KeyCode::Esc);
+        assert!(matches!(app.view_state, ViewState::Chats));
+        assert!(app
+            .chats
+            .transcript()
+            .unwrap()
+            .contains("[declined by the user]"));
+    }
+
+    #[test]
+    fn help() {
+        let mut app = conversation();
+        app.help = Some(Help::new(app.contexts()));
+        snapshot("help", app);
+    }
 }
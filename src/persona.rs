@@ -0,0 +1,204 @@
+use crate::chat::ModelParams;
+use crate::config::{self, ConfigError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// A message added to every chat created from the persona
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Seed {
+    // Either user or assistant
+    pub role: String,
+    pub content: String,
+}
+
+/// Chat template with a system prompt and model defaults
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Persona {
+    pub name: String,
+    #[serde(default)]
+    pub system: String,
+    #[serde(flatten)]
+    pub params: ModelParams,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub seed: Vec<Seed>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PersonaFile {
+    #[serde(default)]
+    persona: Vec<Persona>,
+}
+
+// Seeds with any other role would quietly become user messages, so they are refused
+fn parse_file(text: &str) -> Result<PersonaFile, String> {
+    let file: PersonaFile = toml::from_str(text).map_err(|err| err.to_string())?;
+    for persona in &file.persona {
+        if let Some(seed) = persona
+            .seed
+            .iter()
+            .find(|seed| !matches!(seed.role.as_str(), "user" | "assistant"))
+        {
+            return Err(format!(
+                "{} has a seed with role {:?}, it has to be user or assistant",
+                persona.name, seed.role
+            ));
+        }
+    }
+    Ok(file)
+}
+
+/// Persona library stored in `personas.toml`
+pub struct Personas {
+    pub list: Vec<Persona>,
+    // None when there is no config directory, changes are then kept in memory
+    path: Option<PathBuf>,
+}
+
+impl Personas {
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = config::dir().map(|dir| dir.join("personas.toml"));
+        let file = match &path {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+                parse_file(&text)
+                    .map_err(|err| ConfigError::Persona(format!("{}: {}", path.display(), err)))?
+            }
+            _ => PersonaFile::default(),
+        };
+
+        Ok(Self {
+            list: file.persona,
+            path,
+        })
+    }
+
+    /// Personas that are never written to disk
+    #[cfg(test)]
+    pub fn in_memory(list: Vec<Persona>) -> Self {
+        Self { list, path: None }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(path) = &self.path {
+            let file = PersonaFile {
+                persona: self.list.clone(),
+            };
+            let text = toml::to_string(&file).map_err(|err| err.to_string())?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            fs::write(path, text).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Adds the persona or replaces the one with the same name, returns its index
+    pub fn upsert(&mut self, persona: Persona) -> Result<usize, String> {
+        let index = match self.list.iter().position(|p| p.name == persona.name) {
+            Some(index) => {
+                self.list[index] = persona;
+                index
+            }
+            None => {
+                self.list.push(persona);
+                self.list.len() - 1
+            }
+        };
+        self.save()?;
+        Ok(index)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        if index < self.list.len() {
+            self.list.remove(index);
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::ModelParams;
+    use crate::persona::{parse_file, Persona, PersonaFile, Personas, Seed};
+
+    fn persona(name: &str) -> Persona {
+        Persona {
+            name: name.to_string(),
+            system: "You review code".to_string(),
+            params: ModelParams::default(),
+            seed: vec![],
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let file: PersonaFile = toml::from_str(
+            r#"
+            [[persona]]
+            name = "Translator"
+            system = "Translate to French"
+            model = "gpt-4"
+            temperature = 0.2
+
+            [[persona.seed]]
+            role = "user"
+            content = "Hello"
+            "#,
+        )
+        .unwrap();
+
+        let persona = &file.persona[0];
+        assert_eq!(persona.params.model.as_deref(), Some("gpt-4"));
+        assert_eq!(persona.params.temperature, Some(0.2));
+        assert_eq!(persona.params.max_tokens, None);
+        assert_eq!(
+            persona.seed,
+            vec![Seed {
+                role: "user".to_string(),
+                content: "Hello".to_string()
+            }]
+        );
+
+        // Writing it back keeps everything
+        let text = toml::to_string(&file).unwrap();
+        let again: PersonaFile = toml::from_str(&text).unwrap();
+        assert_eq!(again.persona, file.persona);
+
+        let err = parse_file(
+            r#"
+            [[persona]]
+            name = "Typo"
+
+            [[persona.seed]]
+            role = "asistant"
+            content = "Hello"
+            "#,
+        )
+        .err();
+        assert_eq!(
+            err.as_deref(),
+            Some("Typo has a seed with role \"asistant\", it has to be user or assistant")
+        );
+    }
+
+    #[test]
+    fn manage() {
+        // Without a path nothing is written to disk
+        let mut personas = Personas {
+            list: vec![persona("Reviewer")],
+            path: None,
+        };
+        assert_eq!(personas.upsert(persona("SQL")), Ok(1));
+
+        let mut edited = persona("Reviewer");
+        edited.system = "Be strict".to_string();
+        assert_eq!(personas.upsert(edited), Ok(0));
+        assert_eq!(personas.list[0].system, "Be strict");
+
+        personas.remove(0).unwrap();
+        assert_eq!(personas.list.len(), 1);
+        assert_eq!(personas.list[0].name, "SQL");
+    }
+}
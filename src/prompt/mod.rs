@@ -1,47 +1,131 @@
 mod scroll_prompt;
 mod static_prompt;
+mod undo;
+mod vim;
+mod wrap_prompt;
 
-use arboard::Clipboard;
+use crate::clipboard::Clipboard;
+use crate::keymap::{keymap, Action, Context};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 pub use scroll_prompt::ScrollPrompt;
 pub use static_prompt::StaticPrompt;
+use std::cell::RefCell;
+use std::ops::Range;
+pub use undo::{Group, Snapshot, Undo};
+pub use vim::{Mode, Vim};
+pub use wrap_prompt::WrapPrompt;
 
-// TODO: implement three types of prompts
-//  One that can autoresize box according to total text
-//  One that auto scrolls to the right as i type beyond boundaries - easier
-//  One that stays static - current
+// Cut texts kept for yanking, oldest are dropped past this
+const KILL_RING_SIZE: usize = 16;
+
+/// Text cut by the readline commands, shared by every prompt like in a shell
+#[derive(Default)]
+struct KillRing {
+    // Newest last
+    entries: Vec<String>,
+    // True right after a cut, a following cut adds to the same entry
+    killing: bool,
+    // Text range and entry of the last yank, only while yank-pop can replace it
+    yank: Option<(Range<usize>, usize)>,
+}
+
+thread_local! {
+    // Only the UI thread edits prompts
+    static KILL_RING: RefCell<KillRing> = RefCell::new(KillRing::default());
+}
+
+/// Byte index of the character after i
+fn next_boundary(text: &str, i: usize) -> usize {
+    text[i..].chars().next().map_or(i, |c| i + c.len_utf8())
+}
+
+/// End of the word after i, words are runs of letters and digits
+fn word_end(text: &str, i: usize) -> usize {
+    let after = text[i..].trim_start_matches(|c: char| !c.is_alphanumeric());
+    text.len() - after.trim_start_matches(char::is_alphanumeric).len()
+}
 
 pub trait Prompt {
     // Process input, also requests a clipboard to process pasting
-    fn input(&mut self, key: &KeyEvent, clipboard: &mut Clipboard) {
-        match key.code {
-            KeyCode::Char(c) => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) && c == 'v' {
-                    let paste = clipboard.get_text().unwrap();
-                    self.add_str(paste.as_str())
-                } else {
-                    self.add_char(c)
+    fn input(&mut self, key: &KeyEvent, clipboard: &mut dyn Clipboard) {
+        let action = keymap().action(Context::Prompt, key);
+        let before = self.snapshot();
+        let mut group = match action {
+            Some(Action::Backspace | Action::Delete) => Some(Group::Deleting),
+            _ => None,
+        };
+        match action {
+            Some(Action::Paste) => {
+                if let Some(paste) = clipboard.get_text() {
+                    self.paste(&paste)
                 }
             }
-            KeyCode::Backspace => self.remove_char(),
-            KeyCode::Right => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.ctrl_right()
-                } else {
-                    self.right()
-                }
+            Some(Action::Backspace) => self.remove_char(),
+            Some(Action::Delete) => self.delete(),
+            Some(Action::Newline) => self.newline(),
+            Some(Action::Right) => self.right(),
+            Some(Action::WordRight) => self.ctrl_right(),
+            Some(Action::Left) => self.left(),
+            Some(Action::WordLeft) => self.ctrl_left(),
+            Some(Action::LineEnd) => self.up(),
+            Some(Action::LineStart) => self.down(),
+            Some(Action::StartOfLine) => self.start_of_line(),
+            Some(Action::EndOfLine) => self.end_of_line(),
+            Some(Action::KillToEnd) => self.kill_to_end(),
+            Some(Action::KillToStart) => self.kill_to_start(),
+            Some(Action::KillWord) => {
+                let at = self.caret();
+                self.kill(at..word_end(self.content(), at), false)
+            }
+            Some(Action::KillWordBack) => {
+                // Like the shell, words end at whitespace here
+                let at = self.caret();
+                let before = self.content()[..at].trim_end();
+                let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+                self.kill(start..at, true)
             }
-            KeyCode::Left => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.ctrl_left()
-                } else {
-                    self.left()
+            Some(Action::Yank) => self.yank(),
+            Some(Action::YankPop) => self.yank_pop(),
+            Some(Action::Undo) => self.undo(),
+            Some(Action::Redo) => self.redo(),
+            _ => {
+                // Anything unbound that writes a character is text
+                if let KeyCode::Char(c) = key.code {
+                    if !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                    {
+                        self.add_char(c);
+                        group = Some(Group::Typing);
+                    }
                 }
             }
-            KeyCode::Up => self.up(),
-            KeyCode::Down => self.down(),
-            _ => {}
         }
+
+        match action {
+            Some(Action::Undo | Action::Redo) => {}
+            _ if self.content() != before.text => self.undo_stack().record(before, group),
+            _ => self.undo_stack().end_step(),
+        }
+
+        // Cuts only add up and yanks only pop while they follow each other
+        KILL_RING.with(|ring| {
+            let mut ring = ring.borrow_mut();
+            if !matches!(
+                action,
+                Some(
+                    Action::KillToEnd
+                        | Action::KillToStart
+                        | Action::KillWord
+                        | Action::KillWordBack
+                )
+            ) {
+                ring.killing = false;
+            }
+            if !matches!(action, Some(Action::Yank | Action::YankPop)) {
+                ring.yank = None;
+            }
+        });
     }
 
     fn is_empty(&self) -> bool;
@@ -62,9 +146,375 @@ pub trait Prompt {
 
     fn ctrl_right(&mut self);
 
+    // Places the cursor on a column of the visible text, used for mouse clicks
+    fn move_to(&mut self, column: usize);
+
     fn add_char(&mut self, c: char);
 
     fn add_str(&mut self, s: &str);
 
     fn remove_char(&mut self);
+
+    // Only multi-line prompts accept line breaks
+    fn newline(&mut self) {}
+
+    // Inserts pasted text, whatever line endings it uses
+    fn paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            self.add_str(line);
+        }
+    }
+
+    // The whole text, not only the visible part
+    fn content(&self) -> &str;
+
+    // Byte index of the cursor in the whole text
+    fn caret(&self) -> usize;
+
+    // Replaces the text and moves the cursor, the readline commands are built on it
+    fn set_content(&mut self, text: String, caret: usize);
+
+    // Edits of this prompt that can be undone
+    fn undo_stack(&mut self) -> &mut Undo;
+
+    // How far the text is scrolled, undo brings it back
+    fn view(&self) -> usize {
+        0
+    }
+
+    fn set_view(&mut self, _view: usize) {}
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            text: self.content().to_string(),
+            caret: self.caret(),
+            view: self.view(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.set_content(snapshot.text, snapshot.caret);
+        self.set_view(snapshot.view);
+    }
+
+    // Starts an undo step for changes made outside input, like terminal pastes
+    fn checkpoint(&mut self) {
+        let snapshot = self.snapshot();
+        self.undo_stack().record(snapshot, None);
+    }
+
+    fn undo(&mut self) {
+        let current = self.snapshot();
+        if let Some(before) = self.undo_stack().undo(current) {
+            self.restore(before);
+        }
+    }
+
+    fn redo(&mut self) {
+        let current = self.snapshot();
+        if let Some(after) = self.undo_stack().redo(current) {
+            self.restore(after);
+        }
+    }
+
+    // Replaces a range of the text and leaves the cursor after the new text
+    fn replace_range(&mut self, range: Range<usize>, with: &str) -> String {
+        let mut text = self.content().to_string();
+        let removed = text[range.clone()].to_string();
+        text.replace_range(range.clone(), with);
+        self.set_content(text, range.start + with.len());
+        removed
+    }
+
+    fn delete(&mut self) {
+        let at = self.caret();
+        let end = next_boundary(self.content(), at);
+        self.replace_range(at..end, "");
+    }
+
+    // Lines end at line breaks, not where the text wraps
+    fn start_of_line(&mut self) {
+        let at = self.caret();
+        let start = self.content()[..at].rfind('\n').map_or(0, |i| i + 1);
+        self.set_content(self.content().to_string(), start);
+    }
+
+    fn end_of_line(&mut self) {
+        let at = self.caret();
+        let end = self.content()[at..]
+            .find('\n')
+            .map_or(self.content().len(), |i| at + i);
+        self.set_content(self.content().to_string(), end);
+    }
+
+    // Cuts the range into the kill ring, before tells which side it grows on
+    fn kill(&mut self, range: Range<usize>, before: bool) {
+        if range.is_empty() {
+            return;
+        }
+        let text = self.replace_range(range, "");
+        KILL_RING.with(|ring| {
+            let mut ring = ring.borrow_mut();
+            let killing = ring.killing;
+            match ring.entries.last_mut() {
+                Some(last) if killing && before => last.insert_str(0, &text),
+                Some(last) if killing => last.push_str(&text),
+                _ => {
+                    ring.entries.push(text);
+                    let extra = ring.entries.len().saturating_sub(KILL_RING_SIZE);
+                    ring.entries.drain(..extra);
+                }
+            }
+            ring.killing = true;
+        });
+    }
+
+    // A line break right at the cursor is cut on its own
+    fn kill_to_end(&mut self) {
+        let at = self.caret();
+        let end = match self.content()[at..].find('\n') {
+            Some(0) => at + 1,
+            Some(i) => at + i,
+            None => self.content().len(),
+        };
+        self.kill(at..end, false);
+    }
+
+    fn kill_to_start(&mut self) {
+        let at = self.caret();
+        let start = self.content()[..at].rfind('\n').map_or(0, |i| i + 1);
+        self.kill(start..at, true);
+    }
+
+    // Inserts the newest cut text
+    fn yank(&mut self) {
+        let entry = KILL_RING.with(|ring| ring.borrow().entries.last().cloned());
+        if let Some(text) = entry {
+            let at = self.caret();
+            self.replace_range(at..at, &text);
+            KILL_RING.with(|ring| {
+                let mut ring = ring.borrow_mut();
+                ring.yank = Some((at..at + text.len(), ring.entries.len() - 1));
+            });
+        }
+    }
+
+    // Swaps the text just yanked for the cut before it
+    fn yank_pop(&mut self) {
+        let next = KILL_RING.with(|ring| {
+            let ring = ring.borrow();
+            let (range, index) = ring.yank.clone()?;
+            let index = index.checked_sub(1).unwrap_or(ring.entries.len() - 1);
+            Some((range, index, ring.entries[index].clone()))
+        });
+        if let Some((range, index, text)) = next {
+            // The range is stale if the text changed since
+            if range.end > self.content().len() {
+                return;
+            }
+            let start = range.start;
+            self.replace_range(range, &text);
+            KILL_RING
+                .with(|ring| ring.borrow_mut().yank = Some((start..start + text.len(), index)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clipboard::{Clipboard, Memory};
+    use crate::prompt::{Prompt, ScrollPrompt, StaticPrompt, WrapPrompt};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    // Presses keys written like "ctrl+k" or "alt+y", anything else is typed
+    fn press(prompt: &mut dyn Prompt, keys: &[&str]) {
+        let mut clipboard = Memory::default();
+        for key in keys {
+            let key = match key.split_once('+') {
+                Some(("ctrl", c)) => KeyEvent::new(
+                    KeyCode::Char(c.chars().next().unwrap()),
+                    KeyModifiers::CONTROL,
+                ),
+                Some(("alt", c)) => {
+                    KeyEvent::new(KeyCode::Char(c.chars().next().unwrap()), KeyModifiers::ALT)
+                }
+                _ => match *key {
+                    "home" => KeyEvent::new(KeyCode::Home, KeyModifiers::NONE),
+                    "end" => KeyEvent::new(KeyCode::End, KeyModifiers::NONE),
+                    "delete" => KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE),
+                    c => {
+                        KeyEvent::new(KeyCode::Char(c.chars().next().unwrap()), KeyModifiers::NONE)
+                    }
+                },
+            };
+            prompt.input(&key, &mut clipboard);
+        }
+    }
+
+    fn static_prompt(s: &str) -> StaticPrompt {
+        let mut p = StaticPrompt::new();
+        p.add_str(s);
+        p
+    }
+
+    #[test]
+    fn undo_static() {
+        let mut p = static_prompt("");
+        // Typing is undone as one step, moving starts a new one
+        press(&mut p, &["h", "i", " ", "y", "o", "u", "ctrl+a", ">"]);
+        assert_eq!(p.text, ">hi you");
+        press(&mut p, &["ctrl+z"]);
+        assert_eq!((p.text.as_str(), p.cursor), ("hi you", 0));
+        press(&mut p, &["ctrl+z", "ctrl+z"]);
+        assert_eq!(p.text, "");
+        press(&mut p, &["alt+z", "alt+z"]);
+        assert_eq!((p.text.as_str(), p.cursor), (">hi you", 1));
+
+        // A held backspace comes back at once, a new edit drops the redo
+        press(&mut p, &["end", "ctrl+h", "ctrl+h", "ctrl+h", "ctrl+7"]);
+        assert_eq!(p.text, ">hi you");
+        press(&mut p, &["!", "alt+z"]);
+        assert_eq!(p.text, ">hi you!");
+
+        // A large paste is its own step
+        let mut clipboard = Memory::default();
+        clipboard.set_text(&"x".repeat(10_000)).unwrap();
+        let paste = KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL);
+        p.input(&paste, &mut clipboard);
+        assert_eq!(p.text.len(), 10_008);
+        press(&mut p, &["ctrl+z"]);
+        assert_eq!((p.text.as_str(), p.cursor), (">hi you!", 8));
+
+        // Sending the prompt starts a new history
+        p.flush();
+        press(&mut p, &["ctrl+z"]);
+        assert_eq!(p.text, "");
+    }
+
+    #[test]
+    fn undo_scroll() {
+        let mut p = ScrollPrompt::new(4);
+        press(
+            &mut p,
+            &["h", "e", "l", "l", "o", " ", "w", "o", "r", "l", "d"],
+        );
+        assert_eq!((p.cursor, p.offset), (4, 7));
+        press(&mut p, &["ctrl+a", "ctrl+k"]);
+        assert_eq!(p.text, "");
+
+        // The cursor and the scroll come back with the text
+        press(&mut p, &["ctrl+z"]);
+        assert_eq!(p.text, "hello world");
+        assert_eq!((p.cursor, p.offset), (0, 0));
+        press(&mut p, &["end", "ctrl+w"]);
+        assert_eq!((p.text.as_str(), p.offset), ("hello ", 2));
+        press(&mut p, &["ctrl+z"]);
+        assert_eq!(p.text, "hello world");
+        assert_eq!((p.cursor, p.offset), (4, 7));
+        assert_eq!(p.str(), "orld");
+
+        // Terminal pastes are undone with checkpoints
+        p.checkpoint();
+        p.paste("one\ntwo");
+        press(&mut p, &["ctrl+z"]);
+        assert_eq!(p.text, "hello world");
+    }
+
+    #[test]
+    fn movement() {
+        let mut p = static_prompt("say hello");
+        press(&mut p, &["ctrl+a", ">", "end", "!"]);
+        assert_eq!(p.text, ">say hello!");
+        press(&mut p, &["home", "delete", "ctrl+d"]);
+        assert_eq!(p.text, "ay hello!");
+        // Deleting at the end does nothing
+        press(&mut p, &["ctrl+e", "delete"]);
+        assert_eq!(p.text, "ay hello!");
+    }
+
+    #[test]
+    fn kill_and_yank() {
+        let mut p = static_prompt("one two three");
+        press(&mut p, &["ctrl+w"]);
+        assert_eq!(p.text, "one two ");
+        press(&mut p, &["ctrl+a", "ctrl+k"]);
+        assert_eq!(p.text, "");
+
+        // The latest cut comes back first, alt+y swaps it for the one before
+        press(&mut p, &["ctrl+y"]);
+        assert_eq!(p.text, "one two ");
+        press(&mut p, &["alt+y"]);
+        assert_eq!(p.text, "three");
+        press(&mut p, &["alt+y"]);
+        assert_eq!(p.text, "one two ");
+
+        // Yank-pop only works right after a yank
+        press(&mut p, &["x", "alt+y"]);
+        assert_eq!(p.text, "one two x");
+    }
+
+    #[test]
+    fn consecutive_kills() {
+        let mut p = static_prompt("a b c");
+        // Both cuts end up in one entry, in the order of the text
+        press(&mut p, &["ctrl+w", "ctrl+w", "ctrl+y"]);
+        assert_eq!(p.text, "a b c");
+        press(&mut p, &["ctrl+a", "ctrl+k", "ctrl+y", "ctrl+y"]);
+        assert_eq!(p.text, "a b ca b c");
+    }
+
+    #[test]
+    fn words() {
+        let mut p = static_prompt("fn main() {}");
+        press(&mut p, &["home", "alt+d"]);
+        assert_eq!(p.text, " main() {}");
+        press(&mut p, &["alt+d"]);
+        assert_eq!(p.text, "() {}");
+        press(&mut p, &["end", "ctrl+u"]);
+        assert_eq!(p.text, "");
+    }
+
+    #[test]
+    fn scrolling() {
+        let mut p = ScrollPrompt::new(4);
+        p.add_str("hello world");
+        press(&mut p, &["ctrl+a"]);
+        assert_eq!((p.cursor, p.offset), (0, 0));
+        press(&mut p, &["ctrl+e"]);
+        assert_eq!(p.cursor + p.offset, 11);
+        assert_eq!(p.str(), "orld");
+
+        // The view follows the cursor as text is cut
+        press(&mut p, &["ctrl+w"]);
+        assert_eq!(p.text, "hello ");
+        assert_eq!(p.cursor + p.offset, 6);
+        // Following cuts join the same entry
+        press(&mut p, &["ctrl+u", "ctrl+y"]);
+        assert_eq!(p.text, "hello world");
+        assert_eq!((p.cursor, p.offset), (4, 7));
+    }
+
+    #[test]
+    fn lines() {
+        let mut p = WrapPrompt::new(20, 5);
+        p.add_str("first\nsecond");
+        // Line commands stop at line breaks
+        press(&mut p, &["ctrl+a"]);
+        assert_eq!(p.cursor, 6);
+        press(&mut p, &["ctrl+k"]);
+        assert_eq!(p.text, "first\n");
+        // At the end of a line the break is cut
+        press(&mut p, &["ctrl+b", "ctrl+k"]);
+        assert_eq!(p.text, "first");
+        press(&mut p, &["ctrl+u"]);
+        assert_eq!(p.text, "");
+        // Unicode stays whole
+        p.add_str("héllo");
+        press(&mut p, &["ctrl+a", "ctrl+f", "delete"]);
+        assert_eq!(p.text, "hllo");
+    }
 }
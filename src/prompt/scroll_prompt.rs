@@ -1,4 +1,4 @@
-use crate::prompt::Prompt;
+use crate::prompt::{Prompt, Undo};
 
 pub struct ScrollPrompt {
     pub text: String,
@@ -8,6 +8,7 @@ pub struct ScrollPrompt {
     pub cursor: usize,
     // Prompt front cutoff
     pub offset: usize,
+    undo: Undo,
 }
 
 impl ScrollPrompt {
@@ -17,11 +18,13 @@ impl ScrollPrompt {
             size: size as usize,
             cursor: 0,
             offset: 0,
+            undo: Undo::default(),
         }
     }
 
     pub fn update_size(&mut self, size: u16) {
-        let size = size as usize;
+        // Keep a column on screens too small for the prompt
+        let size = (size as usize).max(1);
         if self.size != size {
             let old = self.real_cursor();
 
@@ -40,10 +43,14 @@ impl ScrollPrompt {
     }
 
     // End of line
-    fn eol(&self) -> bool {
+    pub fn eol(&self) -> bool {
         (self.cursor + self.offset) == self.text.len()
     }
 
+    pub fn at_start(&self) -> bool {
+        self.real_cursor() == 0
+    }
+
     fn real_cursor(&self) -> usize {
         self.cursor + self.offset
     }
@@ -82,6 +89,7 @@ impl Prompt for ScrollPrompt {
         let s = self.text.clone();
         self.down();
         self.text.clear();
+        self.undo = Undo::default();
         s
     }
 
@@ -143,6 +151,10 @@ impl Prompt for ScrollPrompt {
         }
     }
 
+    fn move_to(&mut self, column: usize) {
+        self.cursor = column.min(self.size).min(self.text.len() - self.offset);
+    }
+
     fn add_char(&mut self, c: char) {
         self.text.insert(self.cursor + self.offset, c);
         self.right();
@@ -155,7 +167,8 @@ impl Prompt for ScrollPrompt {
             self.overflow_right(s.len())
         } else if c == self.text.len() {
             self.text += s;
-            self.offset = self.max_offset();
+            // Stay at the end
+            self.up();
         } else {
             let (left, right) = self.text.split_at(c);
             self.text = left.to_owned() + s + right;
@@ -172,11 +185,54 @@ impl Prompt for ScrollPrompt {
             }
         }
     }
+
+    fn content(&self) -> &str {
+        &self.text
+    }
+
+    fn caret(&self) -> usize {
+        self.real_cursor()
+    }
+
+    // Scrolls as little as possible to keep the cursor visible
+    fn set_content(&mut self, text: String, caret: usize) {
+        self.text = text;
+        let caret = caret.min(self.text.len());
+        self.offset = self.offset.min(self.max_offset());
+        if caret < self.offset {
+            self.offset = caret;
+        } else if caret > self.offset + self.size {
+            self.offset = caret - self.size;
+        }
+        self.cursor = caret - self.offset;
+    }
+
+    fn undo_stack(&mut self) -> &mut Undo {
+        &mut self.undo
+    }
+
+    fn view(&self) -> usize {
+        self.offset
+    }
+
+    // Only scrolls back when the cursor stays on screen
+    fn set_view(&mut self, view: usize) {
+        let caret = self.real_cursor();
+        if view <= caret && caret <= view + self.size && view <= self.max_offset() {
+            self.offset = view;
+            self.cursor = caret - view;
+        }
+    }
+
+    // Line breaks are sent with the prompt, the input box shows them as symbols
+    fn newline(&mut self) {
+        self.add_char('\n');
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::prompt::{Prompt, ScrollPrompt};
+    use crate::prompt::{Prompt, ScrollPrompt, Undo};
 
     fn prompt(s: &str) -> ScrollPrompt {
         ScrollPrompt {
@@ -184,6 +240,7 @@ mod tests {
             size: 5,
             cursor: 0,
             offset: 0,
+            undo: Undo::default(),
         }
     }
 
@@ -345,6 +402,23 @@ mod tests {
         assert_eq!(p.cursor, 4);
     }
 
+    #[test]
+    fn move_to() {
+        let mut p = prompt("Click somewhere");
+        p.offset = 6;
+        p.move_to(2);
+        assert_eq!(p.real_cursor(), 8);
+
+        // Clicking past the box stays inside it
+        p.move_to(20);
+        assert_eq!(p.cursor, 5);
+
+        // Clicking past the text goes to the end
+        let mut p = prompt("abc");
+        p.move_to(4);
+        assert_eq!(p.cursor, 3);
+    }
+
     #[test]
     fn add_text() {
         let mut p = prompt("");
@@ -429,6 +503,18 @@ mod tests {
         assert_eq!(p.str(), "this text");
     }
 
+    #[test]
+    fn paste_lines() {
+        let mut p = prompt("Hi");
+        p.up();
+        p.paste("\r\nline one\rline two");
+        assert_eq!(p.text, "Hi\nline one\nline two");
+        // The cursor follows the pasted text
+        assert_eq!(p.real_cursor(), p.text.len());
+        p.add_char('!');
+        assert_eq!(p.text, "Hi\nline one\nline two!");
+    }
+
     #[test]
     fn update_size() {
         let mut p = prompt("123456789");
@@ -1,8 +1,9 @@
-use crate::prompt::Prompt;
+use crate::prompt::{Prompt, Undo};
 
 pub struct StaticPrompt {
     pub text: String,
     pub cursor: usize,
+    undo: Undo,
 }
 
 impl StaticPrompt {
@@ -10,6 +11,7 @@ impl StaticPrompt {
         Self {
             text: String::default(),
             cursor: 0,
+            undo: Undo::default(),
         }
     }
 }
@@ -27,6 +29,7 @@ impl Prompt for StaticPrompt {
         let s = self.text.clone();
         self.cursor = 0;
         self.text.clear();
+        self.undo = Undo::default();
         s
     }
 
@@ -66,6 +69,10 @@ impl Prompt for StaticPrompt {
         }
     }
 
+    fn move_to(&mut self, column: usize) {
+        self.cursor = column.min(self.text.len());
+    }
+
     fn add_char(&mut self, c: char) {
         self.text.insert(self.cursor, c);
         self.right();
@@ -91,17 +98,40 @@ impl Prompt for StaticPrompt {
             self.left();
         }
     }
+
+    fn content(&self) -> &str {
+        &self.text
+    }
+
+    fn caret(&self) -> usize {
+        self.cursor
+    }
+
+    fn set_content(&mut self, text: String, caret: usize) {
+        self.cursor = caret.min(text.len());
+        self.text = text;
+    }
+
+    fn undo_stack(&mut self) -> &mut Undo {
+        &mut self.undo
+    }
+
+    // Fields hold a single line, pasted lines are joined
+    fn paste(&mut self, text: &str) {
+        let lines: Vec<&str> = text.split(['\r', '\n']).filter(|l| !l.is_empty()).collect();
+        self.add_str(&lines.join(" "));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::prompt::{Prompt, StaticPrompt};
-    use async_openai::types::Stop::String;
+    use crate::prompt::{Prompt, StaticPrompt, Undo};
 
     fn prompt(s: &str) -> StaticPrompt {
         StaticPrompt {
             text: s.to_string(),
             cursor: 0,
+            undo: Undo::default(),
         }
     }
 
@@ -114,6 +144,14 @@ mod tests {
         assert_eq!(p.str().len(), 0);
     }
 
+    #[test]
+    fn paste_lines() {
+        let mut p = prompt("");
+        p.paste("gpt-4\r\n\nturbo\n");
+        assert_eq!(p.text, "gpt-4 turbo");
+        assert_eq!(p.cursor, p.text.len());
+    }
+
     #[test]
     fn up() {
         let mut p = prompt("Go up");
@@ -172,6 +210,17 @@ mod tests {
         assert_eq!(p.cursor, 8);
     }
 
+    #[test]
+    fn move_to() {
+        let mut p = prompt("Click");
+        p.move_to(2);
+        assert_eq!(p.cursor, 2);
+
+        // Clicking past the text goes to the end
+        p.move_to(20);
+        assert_eq!(p.cursor, 5);
+    }
+
     #[test]
     fn add_text() {
         let mut p = StaticPrompt::new();
@@ -0,0 +1,65 @@
+// Oldest steps are forgotten past this
+const LIMIT: usize = 200;
+
+/// A prompt's text and cursor at one point, with how far it was scrolled
+#[derive(Clone, PartialEq, Debug)]
+pub struct Snapshot {
+    pub text: String,
+    pub caret: usize,
+    pub view: usize,
+}
+
+/// Edits that join the step before them when repeated
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Group {
+    Typing,
+    Deleting,
+}
+
+/// Edits of one prompt that can be undone and redone
+#[derive(Default)]
+pub struct Undo {
+    // States before each step, newest last
+    undo: Vec<Snapshot>,
+    // States undone, newest last
+    redo: Vec<Snapshot>,
+    // Kind of the last edit, the next one of the same kind joins its step
+    group: Option<Group>,
+}
+
+impl Undo {
+    /// Keeps the state from before an edit, typing or deleting again joins the last step
+    pub fn record(&mut self, before: Snapshot, group: Option<Group>) {
+        if group.is_none() || group != self.group {
+            self.undo.push(before);
+            let extra = self.undo.len().saturating_sub(LIMIT);
+            self.undo.drain(..extra);
+        }
+        self.group = group;
+        self.redo.clear();
+    }
+
+    /// Moving around ends the current step
+    pub fn end_step(&mut self) {
+        self.group = None;
+    }
+
+    /// The state to go back to, steps that didn't change the text are skipped
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.group = None;
+        while let Some(before) = self.undo.pop() {
+            if before.text != current.text {
+                self.redo.push(current);
+                return Some(before);
+            }
+        }
+        None
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.group = None;
+        let after = self.redo.pop()?;
+        self.undo.push(current);
+        Some(after)
+    }
+}
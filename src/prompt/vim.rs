@@ -0,0 +1,479 @@
+use crate::prompt::Prompt;
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+/// Vim style modal editing on top of any prompt, keys typed in insert mode go
+/// to the prompt as usual
+pub struct Vim {
+    pub mode: Mode,
+    // Count typed so far
+    count: Option<usize>,
+    // Operator waiting for its motion with the count typed before it
+    operator: Option<(char, usize)>,
+    // f, t, F or T waiting for its character with its count
+    find: Option<(char, usize)>,
+    // Where the visual selection started
+    anchor: usize,
+    // Text deleted or yanked last
+    register: String,
+}
+
+// Whitespace, word characters and punctuation, words are runs of one class
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn next_char(text: &str, i: usize) -> usize {
+    text[i..].chars().next().map_or(i, |c| i + c.len_utf8())
+}
+
+fn previous_char(text: &str, i: usize) -> usize {
+    text[..i].char_indices().next_back().map_or(0, |(i, _)| i)
+}
+
+fn line_start(text: &str, i: usize) -> usize {
+    text[..i].rfind('\n').map_or(0, |n| n + 1)
+}
+
+fn line_end(text: &str, i: usize) -> usize {
+    text[i..].find('\n').map_or(text.len(), |n| i + n)
+}
+
+/// Where a motion lands from `at`, and whether an operator includes the character there
+fn motion(text: &str, at: usize, key: char, count: usize) -> Option<(usize, bool)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let n = chars.len();
+    let byte = |k: usize| chars.get(k).map_or(text.len(), |(b, _)| *b);
+    let mut k = chars.iter().position(|(b, _)| *b >= at).unwrap_or(n);
+
+    let inclusive = match key {
+        'h' => {
+            k = k.saturating_sub(count);
+            false
+        }
+        'l' => {
+            k = (k + count).min(n);
+            false
+        }
+        'w' => {
+            for _ in 0..count {
+                if k < n && class(chars[k].1) != 0 {
+                    let word = class(chars[k].1);
+                    while k < n && class(chars[k].1) == word {
+                        k += 1;
+                    }
+                }
+                while k < n && class(chars[k].1) == 0 {
+                    k += 1;
+                }
+            }
+            false
+        }
+        'b' => {
+            for _ in 0..count {
+                k = k.saturating_sub(1);
+                while k > 0 && class(chars[k].1) == 0 {
+                    k -= 1;
+                }
+                while k > 0 && class(chars[k - 1].1) == class(chars[k].1) {
+                    k -= 1;
+                }
+            }
+            false
+        }
+        'e' => {
+            for _ in 0..count {
+                k += 1;
+                while k < n && class(chars[k].1) == 0 {
+                    k += 1;
+                }
+                while k + 1 < n && class(chars[k + 1].1) == class(chars[k].1) {
+                    k += 1;
+                }
+            }
+            k = k.min(n.saturating_sub(1));
+            true
+        }
+        '0' => return Some((line_start(text, at), false)),
+        '$' => return Some((line_end(text, at), false)),
+        _ => return None,
+    };
+    Some((byte(k), inclusive))
+}
+
+/// Target of f, t, F or T looking for `target` on the cursor's line
+fn find(text: &str, at: usize, kind: char, target: char, count: usize) -> Option<(usize, bool)> {
+    let line = &text[line_start(text, at)..line_end(text, at)];
+    let offset = line_start(text, at);
+    let mut found = at;
+    for _ in 0..count {
+        found = match kind {
+            'f' | 't' => {
+                // On the line break the search would start past the line
+                let from = next_char(text, found).min(offset + line.len());
+                from + line[from - offset..].find(target)?
+            }
+            _ => offset + line[..found - offset].rfind(target)?,
+        };
+    }
+    Some(match kind {
+        'f' => (found, true),
+        't' => (previous_char(text, found), true),
+        'F' => (found, false),
+        _ => (next_char(text, found), false),
+    })
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Insert,
+            count: None,
+            operator: None,
+            find: None,
+            anchor: 0,
+            register: String::new(),
+        }
+    }
+
+    /// Shown in the title of the input box
+    pub fn name(&self) -> &'static str {
+        match self.mode {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+
+    /// Back to normal mode, returns true when it already was there with nothing pending
+    pub fn escape(&mut self, prompt: &mut dyn Prompt) -> bool {
+        let idle = self.mode == Mode::Normal
+            && self.count.is_none()
+            && self.operator.is_none()
+            && self.find.is_none();
+        if self.mode == Mode::Insert {
+            // Like Vim the cursor steps back onto the last typed character
+            let at = prompt.caret();
+            if at > line_start(prompt.content(), at) {
+                prompt.set_content(
+                    prompt.content().to_string(),
+                    previous_char(prompt.content(), at),
+                );
+            }
+        }
+        self.mode = Mode::Normal;
+        self.reset();
+        idle
+    }
+
+    // Starts in insert mode every time the prompt is opened
+    pub fn start(&mut self) {
+        self.mode = Mode::Insert;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.find = None;
+    }
+
+    // Changes are steps of the prompt's own undo, a whole insert is one step
+    fn insert(&mut self, prompt: &mut dyn Prompt) {
+        prompt.checkpoint();
+        self.mode = Mode::Insert;
+    }
+
+    // Moves the cursor without changing the text
+    fn go(prompt: &mut dyn Prompt, at: usize) {
+        prompt.set_content(prompt.content().to_string(), at);
+    }
+
+    fn cut(&mut self, prompt: &mut dyn Prompt, range: Range<usize>) {
+        prompt.checkpoint();
+        self.register = prompt.replace_range(range, "");
+    }
+
+    // Applies the pending operator to a range
+    fn operate(&mut self, prompt: &mut dyn Prompt, operator: char, range: Range<usize>) {
+        match operator {
+            'y' => {
+                self.register = prompt.content()[range.clone()].to_string();
+                Self::go(prompt, range.start);
+            }
+            'c' => {
+                self.cut(prompt, range);
+                self.mode = Mode::Insert;
+            }
+            _ => self.cut(prompt, range),
+        }
+    }
+
+    /// Handles a character typed outside insert mode
+    pub fn key(&mut self, prompt: &mut dyn Prompt, c: char) {
+        let at = prompt.caret();
+        let text = prompt.content().to_string();
+
+        if let Some((kind, count)) = self.find.take() {
+            let count = count * self.count.take().unwrap_or(1);
+            if let Some(target) = find(&text, at, kind, c, count) {
+                self.land(prompt, at, target);
+            } else {
+                self.reset();
+            }
+            return;
+        }
+        if c.is_ascii_digit() && (c != '0' || self.count.is_some()) {
+            let digit = c.to_digit(10).unwrap() as usize;
+            self.count = Some(self.count.unwrap_or(0) * 10 + digit);
+            return;
+        }
+        let count = self.count.take().unwrap_or(1);
+
+        if matches!(c, 'f' | 't' | 'F' | 'T') {
+            self.find = Some((c, count));
+            return;
+        }
+
+        // Vim treats cw like ce when on a word
+        let key = match (self.operator, c) {
+            (Some(('c', _)), 'w') if text[at..].starts_with(|c: char| !c.is_whitespace()) => 'e',
+            _ => c,
+        };
+        if let Some(target) = motion(&text, at, key, count) {
+            let target = match self.operator.as_mut() {
+                Some((_, first)) => motion(&text, at, key, count * *first).unwrap_or(target),
+                None => target,
+            };
+            self.land(prompt, at, target);
+            return;
+        }
+
+        if self.mode == Mode::Visual {
+            let start = at.min(self.anchor);
+            let end = next_char(&text, at.max(self.anchor));
+            match c {
+                'd' | 'x' | 'c' | 'y' => {
+                    self.mode = Mode::Normal;
+                    self.operate(prompt, if c == 'x' { 'd' } else { c }, start..end);
+                }
+                'v' => self.mode = Mode::Normal,
+                _ => {}
+            }
+            return;
+        }
+
+        if let Some((operator, first)) = self.operator.take() {
+            // Doubled operators work on whole lines
+            if c == operator {
+                let start = line_start(&text, at);
+                let mut end = at;
+                for _ in 0..count * first {
+                    end = next_char(&text, line_end(&text, end));
+                }
+                let end = match operator {
+                    // Keep the line break, the line is replaced
+                    'c' if text[..end].ends_with('\n') => end - 1,
+                    _ => end,
+                };
+                self.operate(prompt, operator, start..end);
+            }
+            return;
+        }
+
+        match c {
+            'd' | 'c' | 'y' => self.operator = Some((c, count)),
+            'i' => self.insert(prompt),
+            'a' => {
+                self.insert(prompt);
+                Self::go(prompt, next_char(&text, at));
+            }
+            'I' => {
+                self.insert(prompt);
+                Self::go(prompt, line_start(&text, at));
+            }
+            'A' => {
+                self.insert(prompt);
+                Self::go(prompt, line_end(&text, at));
+            }
+            'x' => {
+                let end = (0..count).fold(at, |end, _| next_char(&text, end));
+                if end > at {
+                    self.cut(prompt, at..end);
+                }
+            }
+            'X' => {
+                let start = (0..count).fold(at, |start, _| previous_char(&text, start));
+                if start < at {
+                    self.cut(prompt, start..at);
+                }
+            }
+            'D' => self.cut(prompt, at..line_end(&text, at)),
+            'C' => {
+                self.cut(prompt, at..line_end(&text, at));
+                self.mode = Mode::Insert;
+            }
+            'p' | 'P' if !self.register.is_empty() => {
+                prompt.checkpoint();
+                let at = if c == 'p' { next_char(&text, at) } else { at };
+                let put = self.register.repeat(count);
+                prompt.replace_range(at..at, &put);
+                // The cursor ends on the last character put
+                let last = previous_char(prompt.content(), at + put.len());
+                Self::go(prompt, last);
+            }
+            'u' => {
+                for _ in 0..count {
+                    prompt.undo();
+                }
+            }
+            'v' => {
+                self.mode = Mode::Visual;
+                self.anchor = at;
+            }
+            _ => {}
+        }
+    }
+
+    // Moves to a motion's target, or applies the pending operator up to it
+    fn land(&mut self, prompt: &mut dyn Prompt, at: usize, (target, inclusive): (usize, bool)) {
+        match self.operator.take() {
+            Some((operator, _)) => {
+                let text = prompt.content();
+                let range = match target >= at {
+                    true if inclusive => at..next_char(text, target),
+                    true => at..target,
+                    false => target..at,
+                };
+                if !range.is_empty() {
+                    self.operate(prompt, operator, range);
+                }
+            }
+            None => Self::go(prompt, target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::vim::{Mode, Vim};
+    use crate::prompt::{Prompt, StaticPrompt};
+
+    // Types the keys in normal mode, the cursor starts at `at`
+    fn normal(text: &str, at: usize, keys: &str) -> (StaticPrompt, Vim) {
+        let mut prompt = StaticPrompt::new();
+        prompt.set_content(text.to_string(), at);
+        let mut vim = Vim::new();
+        vim.mode = Mode::Normal;
+        for c in keys.chars() {
+            match vim.mode {
+                Mode::Insert => prompt.add_char(c),
+                _ => vim.key(&mut prompt, c),
+            }
+        }
+        (prompt, vim)
+    }
+
+    fn edit(text: &str, at: usize, keys: &str) -> String {
+        normal(text, at, keys).0.text
+    }
+
+    fn cursor(text: &str, at: usize, keys: &str) -> usize {
+        normal(text, at, keys).0.cursor
+    }
+
+    #[test]
+    fn motions() {
+        let text = "fn main(args) {}";
+        assert_eq!(cursor(text, 0, "w"), 3);
+        assert_eq!(cursor(text, 0, "2w"), 7);
+        assert_eq!(cursor(text, 0, "e"), 1);
+        assert_eq!(cursor(text, 0, "ee"), 6);
+        assert_eq!(cursor(text, 12, "b"), 8);
+        assert_eq!(cursor(text, 5, "0"), 0);
+        assert_eq!(cursor(text, 5, "$"), 16);
+        assert_eq!(cursor(text, 0, "f("), 7);
+        assert_eq!(cursor(text, 0, "t("), 6);
+        assert_eq!(cursor(text, 0, "2fa"), 8);
+        assert_eq!(cursor(text, 12, "F("), 7);
+        assert_eq!(cursor(text, 12, "T("), 8);
+        // Missing characters don't move
+        assert_eq!(cursor(text, 0, "fz"), 0);
+        // Only the cursor's line is searched, even from its line break
+        assert_eq!(cursor("ab\ncd", 0, "$fc"), 2);
+        assert_eq!(cursor("ab\ncd", 0, "$Fa"), 0);
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(edit("one two three", 0, "dw"), "two three");
+        assert_eq!(edit("one two three", 0, "d2w"), "three");
+        assert_eq!(edit("one two three", 0, "2dw"), "three");
+        assert_eq!(edit("one two three", 0, "de"), " two three");
+        assert_eq!(edit("one two three", 4, "d$"), "one ");
+        assert_eq!(edit("one two three", 4, "D"), "one ");
+        assert_eq!(edit("say (hi)", 0, "dt("), "(hi)");
+        assert_eq!(edit("say (hi)", 0, "df("), "hi)");
+        assert_eq!(edit("one two", 0, "3x"), " two");
+        assert_eq!(edit("first\nsecond", 2, "dd"), "second");
+
+        // Change leaves insert mode on, cw stops at the end of the word
+        assert_eq!(edit("one two", 0, "cwsix"), "six two");
+        assert_eq!(edit("one\ntwo\nthree", 4, "ccnew"), "one\nnew\nthree");
+        assert_eq!(edit("one two", 4, "Cthree"), "one three");
+
+        // Yank and put
+        assert_eq!(edit("ab", 0, "ylp"), "aab");
+        assert_eq!(edit("one two", 0, "yeP"), "oneone two");
+        assert_eq!(edit("one two", 0, "dwp"), "tone wo");
+    }
+
+    #[test]
+    fn visual() {
+        assert_eq!(edit("one two three", 4, "vld"), "one o three");
+        assert_eq!(edit("one two three", 4, "vey$p"), "one two threetwo");
+        assert_eq!(edit("one two three", 4, "vbc1"), "1wo three");
+        let (_, vim) = normal("one", 0, "vlv");
+        assert_eq!(vim.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn undo() {
+        assert_eq!(edit("one two", 0, "dwu"), "one two");
+        assert_eq!(edit("one two", 0, "dwxuu"), "one two");
+        // A whole insert is undone at once
+        let (mut prompt, mut vim) = normal("one", 3, "a two");
+        vim.escape(&mut prompt);
+        assert_eq!(prompt.text, "one two");
+        vim.key(&mut prompt, 'u');
+        assert_eq!(prompt.text, "one");
+        // Undo is shared with the prompt, so redo brings it back
+        prompt.redo();
+        assert_eq!(prompt.text, "one two");
+        assert_eq!(edit("one two", 0, "dwx2u"), "one two");
+    }
+
+    #[test]
+    fn escape() {
+        let (mut prompt, mut vim) = normal("one", 0, "A!");
+        assert_eq!(vim.mode, Mode::Insert);
+        assert!(!vim.escape(&mut prompt));
+        // The cursor steps back onto the last character typed
+        assert_eq!(prompt.cursor, 3);
+        vim.key(&mut prompt, 'd');
+        assert!(!vim.escape(&mut prompt));
+        assert!(vim.escape(&mut prompt));
+    }
+}
@@ -0,0 +1,385 @@
+use crate::prompt::{Prompt, Undo};
+
+/// Multi-line prompt that wraps its text to the box width and scrolls vertically
+pub struct WrapPrompt {
+    pub text: String,
+    // Byte index of the cursor in the text
+    pub cursor: usize,
+    // Visible columns
+    pub width: usize,
+    // Visible rows
+    pub height: usize,
+    // First visible row
+    pub scroll: usize,
+    undo: Undo,
+}
+
+impl WrapPrompt {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            text: String::default(),
+            cursor: 0,
+            width: width.max(1) as usize,
+            height: height.max(1) as usize,
+            scroll: 0,
+            undo: Undo::default(),
+        }
+    }
+
+    pub fn update_size(&mut self, width: u16, height: u16) {
+        self.width = width.max(1) as usize;
+        self.height = height.max(1) as usize;
+        self.follow();
+    }
+
+    // Byte range of every wrapped row, newlines are left out
+    fn rows(&self) -> Vec<(usize, usize)> {
+        let mut rows = vec![];
+        let mut start = 0;
+        let mut count = 0;
+
+        for (i, c) in self.text.char_indices() {
+            if c == '\n' {
+                rows.push((start, i));
+                start = i + 1;
+                count = 0;
+                continue;
+            }
+            if count == self.width {
+                rows.push((start, i));
+                start = i;
+                count = 0;
+            }
+            count += 1;
+        }
+        // A full last row leaves the cursor on a new one
+        if count == self.width {
+            rows.push((start, self.text.len()));
+            start = self.text.len();
+        }
+        rows.push((start, self.text.len()));
+
+        rows
+    }
+
+    /// Total rows the text needs, used to grow the box
+    pub fn line_count(&self) -> usize {
+        self.rows().len()
+    }
+
+    // Row and column of the cursor
+    fn position(&self) -> (usize, usize) {
+        let rows = self.rows();
+        let row = rows
+            .iter()
+            .rposition(|(start, end)| *start <= self.cursor && self.cursor <= *end)
+            .unwrap_or(0);
+        let col = self.text[rows[row].0..self.cursor].chars().count();
+        (row, col)
+    }
+
+    // Byte index of a column in a row, clamped to the row
+    fn index(&self, row: usize, col: usize) -> usize {
+        let rows = self.rows();
+        let row = row.min(rows.len() - 1);
+        let (start, end) = rows[row];
+        let line = &self.text[start..end];
+        let mut max = line.chars().count();
+        // The end of a wrapped row is the start of the next one
+        if rows.get(row + 1).is_some_and(|next| next.0 == end) {
+            max = max.saturating_sub(1);
+        }
+
+        start
+            + line
+                .char_indices()
+                .nth(col.min(max))
+                .map_or(line.len(), |(i, _)| i)
+    }
+
+    // Scrolls so the cursor row stays visible
+    fn follow(&mut self) {
+        let (row, _) = self.position();
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + self.height {
+            self.scroll = row + 1 - self.height;
+        }
+    }
+
+    /// Rows currently shown in the box
+    pub fn lines(&self) -> Vec<&str> {
+        self.rows()
+            .into_iter()
+            .skip(self.scroll)
+            .take(self.height)
+            .map(|(start, end)| &self.text[start..end])
+            .collect()
+    }
+
+    /// Cursor column and row inside the box
+    pub fn cursor_position(&self) -> (u16, u16) {
+        let (row, col) = self.position();
+        (col as u16, (row - self.scroll) as u16)
+    }
+
+    /// Places the cursor on a visible row and column, used for mouse clicks
+    pub fn click(&mut self, row: usize, column: usize) {
+        self.cursor = self.index(self.scroll + row, column);
+    }
+
+    fn previous_char(&self) -> Option<usize> {
+        self.text[..self.cursor]
+            .chars()
+            .next_back()
+            .map(|c| self.cursor - c.len_utf8())
+    }
+}
+
+impl Prompt for WrapPrompt {
+    fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    fn str(&self) -> &str {
+        self.text.as_str()
+    }
+
+    fn flush(&mut self) -> String {
+        let s = self.text.clone();
+        self.text.clear();
+        self.cursor = 0;
+        self.scroll = 0;
+        self.undo = Undo::default();
+        s
+    }
+
+    fn down(&mut self) {
+        let (row, col) = self.position();
+        if row + 1 < self.line_count() {
+            self.cursor = self.index(row + 1, col);
+        } else {
+            self.cursor = self.text.len();
+        }
+        self.follow();
+    }
+
+    fn up(&mut self) {
+        let (row, col) = self.position();
+        if row > 0 {
+            self.cursor = self.index(row - 1, col);
+        } else {
+            self.cursor = 0;
+        }
+        self.follow();
+    }
+
+    fn left(&mut self) {
+        if let Some(i) = self.previous_char() {
+            self.cursor = i;
+        }
+        self.follow();
+    }
+
+    fn ctrl_left(&mut self) {
+        let (left, _) = self.text.split_at(self.cursor);
+        self.cursor = left
+            .trim_end()
+            .rfind(char::is_whitespace)
+            .map_or(0, |i| i + 1);
+        self.follow();
+    }
+
+    fn right(&mut self) {
+        if let Some(c) = self.text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+        self.follow();
+    }
+
+    fn ctrl_right(&mut self) {
+        let (_, right) = self.text.split_at(self.cursor);
+        let skipped = right.len() - right.trim_start().len();
+        self.cursor += skipped
+            + right[skipped..]
+                .find(char::is_whitespace)
+                .unwrap_or(right.len() - skipped);
+        self.follow();
+    }
+
+    fn move_to(&mut self, column: usize) {
+        let (row, _) = self.position();
+        self.cursor = self.index(row, column);
+    }
+
+    fn add_char(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        self.follow();
+    }
+
+    fn add_str(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+        self.follow();
+    }
+
+    fn remove_char(&mut self) {
+        if let Some(i) = self.previous_char() {
+            self.text.remove(i);
+            self.cursor = i;
+        }
+        self.follow();
+    }
+
+    fn newline(&mut self) {
+        self.add_char('\n');
+    }
+
+    fn content(&self) -> &str {
+        &self.text
+    }
+
+    fn caret(&self) -> usize {
+        self.cursor
+    }
+
+    fn set_content(&mut self, text: String, caret: usize) {
+        self.cursor = caret.min(text.len());
+        self.text = text;
+        self.follow();
+    }
+
+    fn undo_stack(&mut self) -> &mut Undo {
+        &mut self.undo
+    }
+
+    fn view(&self) -> usize {
+        self.scroll
+    }
+
+    fn set_view(&mut self, view: usize) {
+        self.scroll = view;
+        self.follow();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::{Prompt, Undo, WrapPrompt};
+
+    fn prompt(s: &str) -> WrapPrompt {
+        WrapPrompt {
+            text: s.to_string(),
+            cursor: 0,
+            width: 5,
+            height: 2,
+            scroll: 0,
+            undo: Undo::default(),
+        }
+    }
+
+    #[test]
+    fn basic() {
+        let mut p = prompt("Wrapped text");
+        assert_eq!(p.lines(), vec!["Wrapp", "ed te"]);
+        assert_eq!(p.line_count(), 3);
+        assert_eq!(p.flush(), "Wrapped text".to_string());
+        assert!(p.is_empty());
+    }
+
+    #[test]
+    fn newlines() {
+        let mut p = prompt("ab\ncd");
+        assert_eq!(p.lines(), vec!["ab", "cd"]);
+        p.cursor = 2;
+        assert_eq!(p.cursor_position(), (2, 0));
+        p.right();
+        assert_eq!(p.cursor_position(), (0, 1));
+    }
+
+    #[test]
+    fn full_row() {
+        // The cursor after a full row goes to the next one
+        let mut p = prompt("12345");
+        p.cursor = 5;
+        assert_eq!(p.line_count(), 2);
+        assert_eq!(p.cursor_position(), (0, 1));
+    }
+
+    #[test]
+    fn up_down() {
+        let mut p = prompt("123456789");
+        p.cursor = 7;
+        p.up();
+        assert_eq!(p.cursor, 2);
+        // First row goes to the start
+        p.up();
+        assert_eq!(p.cursor, 0);
+
+        p.cursor = 4;
+        p.down();
+        assert_eq!(p.cursor, 9);
+        // Clamped to the end of a shorter row
+        p.cursor = 4;
+        p.up();
+        assert_eq!(p.cursor, 0);
+    }
+
+    #[test]
+    fn scroll() {
+        let mut p = prompt("");
+        p.add_str("123456789abcdef");
+        assert_eq!(p.scroll, 2);
+        assert_eq!(p.lines(), vec!["bcdef", ""]);
+        assert_eq!(p.cursor_position(), (0, 1));
+
+        p.up();
+        p.up();
+        assert_eq!(p.scroll, 1);
+        assert_eq!(p.lines(), vec!["6789a", "bcdef"]);
+    }
+
+    #[test]
+    fn words() {
+        let mut p = prompt("many words here");
+        p.ctrl_right();
+        assert_eq!(p.cursor, 4);
+        p.ctrl_right();
+        assert_eq!(p.cursor, 10);
+        p.ctrl_left();
+        assert_eq!(p.cursor, 5);
+        p.ctrl_left();
+        assert_eq!(p.cursor, 0);
+    }
+
+    #[test]
+    fn unicode() {
+        let mut p = prompt("");
+        p.add_str("héé");
+        p.left();
+        p.remove_char();
+        assert_eq!(p.str(), "hé");
+        assert_eq!(p.cursor, 1);
+    }
+
+    #[test]
+    fn click() {
+        let mut p = prompt("abc\ndefgh");
+        p.click(1, 2);
+        assert_eq!(p.cursor, 6);
+        p.click(0, 9);
+        assert_eq!(p.cursor, 3);
+        p.move_to(1);
+        assert_eq!(p.cursor, 1);
+    }
+
+    #[test]
+    fn newline() {
+        let mut p = prompt("ab");
+        p.cursor = 1;
+        p.newline();
+        assert_eq!(p.str(), "a\nb");
+        assert_eq!(p.cursor_position(), (0, 1));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::chat::ModelParams;
+use crate::config::ProviderConfig;
+use crate::provider::{
+    block_on, plain_text, read_lines, resolve_key, role_name, sse_data, Delta, Part, Provider,
+    DEFAULT_MAX_TOKENS,
+};
+use async_openai::error::{ApiError, OpenAIError};
+use async_openai::types::{ChatCompletionRequestMessage as Message, Role};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::mpsc::Sender;
+
+const ANTHROPIC_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Sent before conversations the assistant starts, the API wants the user first
+const START: &str = "(The conversation starts here.)";
+
+#[derive(Deserialize)]
+struct Event {
+    r#type: String,
+    delta: Option<EventDelta>,
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct EventDelta {
+    text: Option<String>,
+}
+
+/// Anthropic's messages API, answers stream as server sent events
+pub struct Anthropic {
+    http: reqwest::Client,
+    url: String,
+    // Err says why there is no key, a missing key only fails the requests
+    key: Result<String, String>,
+    model: String,
+}
+
+/// Reads one line of the answer, only text deltas, errors and the stop event matter
+fn parse_line(line: &str) -> Option<Delta> {
+    let data = sse_data(line)?;
+    let event: Event = match serde_json::from_str(data) {
+        Ok(event) => event,
+        Err(err) => return Some(Err(OpenAIError::JSONDeserialize(err))),
+    };
+
+    match event.r#type.as_str() {
+        "content_block_delta" => event
+            .delta
+            .and_then(|d| d.text)
+            .map(|t| Ok(Some(Part::Text(t)))),
+        "message_stop" => Some(Ok(None)),
+        "error" => Some(Err(match event.error {
+            Some(error) => OpenAIError::ApiError(error),
+            None => OpenAIError::StreamError(data.to_string()),
+        })),
+        _ => None,
+    }
+}
+
+impl Anthropic {
+    pub fn new(config: &ProviderConfig) -> Result<Self, String> {
+        let ProviderConfig::Anthropic {
+            api_key,
+            api_key_env,
+            base_url,
+            model,
+        } = config
+        else {
+            return Err("not an anthropic profile".to_string());
+        };
+        let url = format!(
+            "{}/v1/messages",
+            base_url
+                .as_deref()
+                .unwrap_or(ANTHROPIC_URL)
+                .trim_end_matches('/')
+        );
+        reqwest::Url::parse(&url).map_err(|err| format!("invalid url {:?}: {}", url, err))?;
+
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url,
+            key: resolve_key(api_key, api_key_env, "ANTHROPIC_API_KEY")
+                .ok_or("no API key, set api_key in the provider or ANTHROPIC_API_KEY".to_string()),
+            model: model.clone(),
+        })
+    }
+
+    // System prompts go in a top level field and the turns have to alternate
+    // starting with the user, so messages from the same side in a row are joined
+    fn body(&self, messages: &[Message], params: &ModelParams) -> Value {
+        let mut system: Vec<&str> = vec![];
+        let mut turns: Vec<(&str, String)> = vec![];
+        for message in messages {
+            match (&message.role, turns.last_mut()) {
+                (Role::System, _) => system.push(&message.content),
+                (role, Some((last, content))) if *last == role_name(role) => {
+                    content.push_str("\n\n");
+                    content.push_str(&plain_text(message));
+                }
+                (role, _) => turns.push((role_name(role), plain_text(message))),
+            }
+        }
+        // Seeds can start with the assistant, a stand in user turn goes first then
+        if turns.first().is_some_and(|(role, _)| *role == "assistant") {
+            turns.insert(0, ("user", START.to_string()));
+        }
+
+        let mut body = json!({
+            "model": params.model.as_deref().unwrap_or(&self.model),
+            "max_tokens": params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": turns
+                .into_iter()
+                .map(|(role, content)| json!({ "role": role, "content": content }))
+                .collect::<Vec<Value>>(),
+            "stream": true,
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system.join("\n\n"));
+        }
+        if let Some(temperature) = params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        body
+    }
+}
+
+impl Provider for Anthropic {
+    fn stream(&self, messages: Vec<Message>, params: &ModelParams, tx: &Sender<Delta>) {
+        block_on(tx, async {
+            let result = match self.key.clone() {
+                Ok(key) => {
+                    let request = self
+                        .http
+                        .post(&self.url)
+                        .header("x-api-key", key)
+                        .header("anthropic-version", ANTHROPIC_VERSION)
+                        .json(&self.body(&messages, params));
+                    read_lines(request, tx, parse_line).await
+                }
+                Err(err) => Err(OpenAIError::InvalidArgument(err)),
+            };
+            let _ = tx.send(result.map(|_| None));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::{assistant_msg, call_msg, result_msg, system_msg, user_msg, ModelParams};
+    use crate::config::ProviderConfig;
+    use crate::provider::anthropic::{parse_line, Anthropic, START};
+    use crate::provider::replay;
+    use async_openai::error::OpenAIError;
+    use serde_json::json;
+
+    #[test]
+    fn fixtures() {
+        let (text, end) = replay(include_str!("fixtures/anthropic_messages.sse"), parse_line);
+        assert_eq!(text, "The sky is blue, ünless it's night.");
+        assert!(matches!(end, Some(Ok(()))));
+
+        let (text, end) = replay(include_str!("fixtures/anthropic_error.sse"), parse_line);
+        assert_eq!(text, "The");
+        assert!(
+            matches!(end, Some(Err(OpenAIError::ApiError(e))) if e.r#type == "overloaded_error")
+        );
+    }
+
+    #[test]
+    fn body() {
+        let anthropic = Anthropic::new(&ProviderConfig::Anthropic {
+            api_key: Some("key".to_string()),
+            api_key_env: None,
+            base_url: None,
+            model: "claude-3-haiku-20240307".to_string(),
+        })
+        .unwrap();
+        assert_eq!(anthropic.url, "https://api.anthropic.com/v1/messages");
+
+        let messages = [
+            system_msg("Be brief".to_string()),
+            user_msg("Hi".to_string()),
+            assistant_msg("Hello".to_string()),
+            user_msg("One".to_string()),
+            user_msg("Two".to_string()),
+        ];
+        assert_eq!(
+            anthropic.body(&messages, &ModelParams::default()),
+            json!({
+                "model": "claude-3-haiku-20240307",
+                "max_tokens": 500,
+                "system": "Be brief",
+                "messages": [
+                    { "role": "user", "content": "Hi" },
+                    { "role": "assistant", "content": "Hello" },
+                    { "role": "user", "content": "One\n\nTwo" },
+                ],
+                "stream": true,
+            })
+        );
+
+        // The first turn has to be the user's
+        let messages = [
+            assistant_msg("How can I help?".to_string()),
+            user_msg("Hi".to_string()),
+        ];
+        assert_eq!(
+            anthropic.body(&messages, &ModelParams::default())["messages"],
+            json!([
+                { "role": "user", "content": START },
+                { "role": "assistant", "content": "How can I help?" },
+                { "role": "user", "content": "Hi" },
+            ])
+        );
+
+        // Calls from other profiles are told as text
+        let messages = [
+            user_msg("What's there?".to_string()),
+            call_msg("list_dir".to_string(), r#"{"path":"."}"#.to_string()),
+            result_msg("list_dir".to_string(), "notes.md".to_string()),
+            user_msg("Thanks".to_string()),
+        ];
+        assert_eq!(
+            anthropic.body(&messages, &ModelParams::default())["messages"],
+            json!([
+                { "role": "user", "content": "What's there?" },
+                { "role": "assistant", "content": r#"[called list_dir with {"path":"."}]"# },
+                { "role": "user", "content": "[result of list_dir]\nnotes.md\n\nThanks" },
+            ])
+        );
+    }
+}
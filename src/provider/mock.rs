@@ -0,0 +1,175 @@
+// Local stand-in for an OpenAI compatible server, it replays scripted answers
+// so the providers and chats can be tested without the network
+
+use crate::chat::ModelParams;
+use crate::provider::{Delta, Part, Provider};
+use async_openai::error::OpenAIError;
+use async_openai::types::ChatCompletionRequestMessage as Message;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// How a scripted answer ends
+pub enum End {
+    // The usual `[DONE]` event
+    Done,
+    // The connection closes without one
+    Disconnect,
+    // An error event with this message
+    Error(&'static str),
+}
+
+/// A scripted response to one request
+pub struct Reply {
+    status: u16,
+    body: String,
+}
+
+/// A streamed chunk shaped like `CreateChatCompletionStreamResponse`
+pub fn chunk(text: &str) -> Value {
+    json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion.chunk",
+        "created": 1680000000,
+        "model": "gpt-3.5-turbo",
+        "choices": [{ "index": 0, "delta": { "content": text }, "finish_reason": null }],
+    })
+}
+
+fn api_error(message: &str) -> Value {
+    json!({ "error": { "message": message, "type": "mock_error", "param": null, "code": null } })
+}
+
+impl Reply {
+    /// Streams every piece as a chunk, then ends the answer
+    pub fn stream(pieces: &[&str], end: End) -> Self {
+        Self::events(pieces.iter().map(|p| chunk(p)), end)
+    }
+
+    /// Streams a function call, the arguments in pieces after the name
+    pub fn call(name: &str, arguments: &[&str]) -> Self {
+        let piece = |call: Value| {
+            let mut chunk = chunk("");
+            chunk["choices"][0]["delta"] = json!({ "content": null, "function_call": call });
+            chunk
+        };
+        let name = piece(json!({ "name": name, "arguments": "" }));
+        let arguments = arguments.iter().map(|a| piece(json!({ "arguments": a })));
+        Self::events(std::iter::once(name).chain(arguments), End::Done)
+    }
+
+    fn events(events: impl Iterator<Item = Value>, end: End) -> Self {
+        let mut body = String::new();
+        // Servers start by sending the role alone
+        let mut role = chunk("");
+        role["choices"][0]["delta"] = json!({ "role": "assistant" });
+        for event in std::iter::once(role).chain(events) {
+            body += &format!("data: {}\n\n", event);
+        }
+        match end {
+            End::Done => body += "data: [DONE]\n\n",
+            End::Disconnect => {}
+            End::Error(message) => body += &format!("data: {}\n\n", api_error(message)),
+        }
+        Self { status: 200, body }
+    }
+
+    /// Refuses the request with an API error
+    pub fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: api_error(message).to_string(),
+        }
+    }
+}
+
+pub struct MockServer {
+    url: String,
+    // Body of every request received
+    requests: Receiver<Value>,
+}
+
+impl MockServer {
+    /// Answers one request with each reply in order, then stops
+    pub fn start(replies: Vec<Reply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let (tx, requests) = channel();
+
+        thread::spawn(move || {
+            for reply in replies {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let _ = tx.send(serde_json::from_slice(&body).unwrap());
+
+                // The connection closing marks the end of the body
+                let mut stream = reader.into_inner();
+                let kind = match reply.status {
+                    200 => "text/event-stream",
+                    _ => "application/json",
+                };
+                let head = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+                    reply.status, kind
+                );
+                let _ = stream.write_all(head.as_bytes());
+                // Split the body so lines arrive in pieces
+                for piece in reply.body.as_bytes().chunks(40) {
+                    let _ = stream.write_all(piece);
+                    let _ = stream.flush();
+                }
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Base url for a provider profile
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Body of the next request the server received
+    pub fn request(&self) -> Value {
+        self.requests.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+}
+
+/// Streams an answer to the end, returns its text and how it ended
+pub fn answer(
+    provider: &dyn Provider,
+    messages: Vec<Message>,
+) -> (String, Result<(), OpenAIError>) {
+    let (tx, rx) = channel::<Delta>();
+    provider.stream(messages, &ModelParams::default(), &tx);
+
+    let mut text = String::new();
+    for delta in rx.try_iter() {
+        match delta {
+            Ok(Some(Part::Text(delta))) => text += &delta,
+            Ok(Some(Part::Call(..))) => {}
+            Ok(None) => return (text, Ok(())),
+            Err(err) => return (text, Err(err)),
+        }
+    }
+    (text, Err(OpenAIError::StreamError("no end".to_string())))
+}
@@ -0,0 +1,318 @@
+mod anthropic;
+#[cfg(test)]
+pub mod mock;
+mod ollama;
+mod openai;
+
+use crate::chat::ModelParams;
+use crate::config::ProviderConfig;
+use anthropic::Anthropic;
+use async_openai::error::{ApiError, OpenAIError};
+use async_openai::types::{ChatCompletionRequestMessage as Message, Role};
+use futures::StreamExt;
+use ollama::Ollama;
+use openai::OpenAi;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::future::Future;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, OnceLock};
+use tokio::runtime::Runtime;
+
+pub const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+pub const DEFAULT_MAX_TOKENS: u16 = 500;
+
+// Profile used when the config has none
+const DEFAULT_PROFILE: &str = "openai";
+
+/// A piece of a streamed answer
+#[derive(Debug, PartialEq)]
+pub enum Part {
+    Text(String),
+    // Pieces of a function call's name and JSON arguments
+    Call(String, String),
+}
+
+impl Part {
+    fn is_empty(&self) -> bool {
+        match self {
+            Part::Text(text) => text.is_empty(),
+            Part::Call(name, arguments) => name.is_empty() && arguments.is_empty(),
+        }
+    }
+}
+
+/// Answer pieces as they stream in, None marks the end of the answer
+pub type Delta = Result<Option<Part>, OpenAIError>;
+
+/// A chat completion service
+pub trait Provider: Send + Sync {
+    /// Sends the messages and streams the answer into tx, blocks until it is over
+    fn stream(&self, messages: Vec<Message>, params: &ModelParams, tx: &Sender<Delta>);
+}
+
+/// Runs a request on the runtime every provider thread shares. The clients keep
+/// their pooled connections on it, so it has to live as long as they do.
+fn block_on<F: Future<Output = ()>>(tx: &Sender<Delta>, future: F) {
+    static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+    let runtime = RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| err.to_string())
+    });
+    // Threads that call this together take turns driving the runtime
+    match runtime {
+        Ok(rt) => rt.block_on(future),
+        Err(err) => {
+            let _ = tx.send(Err(OpenAIError::StreamError(err.clone())));
+        }
+    }
+}
+
+/// Splits a byte stream into lines, chunks can end in the middle of a line or a character
+#[derive(Default)]
+struct LineBuffer {
+    buffer: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = vec![];
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            lines.push(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+        lines
+    }
+}
+
+/// Payload of a server sent event data line
+fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim_start)
+}
+
+/// Sends the request and parses every line of the answer until one ends it.
+/// Lines parse to None when they carry nothing, like keep-alives.
+async fn read_lines<F>(
+    request: reqwest::RequestBuilder,
+    tx: &Sender<Delta>,
+    parse: F,
+) -> Result<(), OpenAIError>
+where
+    F: Fn(&str) -> Option<Delta>,
+{
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await?;
+        return Err(http_error(status, &body));
+    }
+
+    let mut lines = LineBuffer::default();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        for line in lines.push(&chunk?) {
+            match parse(&line) {
+                Some(Ok(Some(part))) if !part.is_empty() => {
+                    let _ = tx.send(Ok(Some(part)));
+                }
+                Some(Ok(None)) => return Ok(()),
+                Some(Err(err)) => return Err(err),
+                _ => {}
+            }
+        }
+    }
+
+    Err(OpenAIError::StreamError(
+        "the connection closed before the answer was over".to_string(),
+    ))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    // OpenAI and Anthropic
+    Api { error: ApiError },
+    // Ollama
+    Text { error: String },
+}
+
+/// Turns an error response into the error it describes
+fn http_error(status: reqwest::StatusCode, body: &str) -> OpenAIError {
+    match serde_json::from_str::<ErrorBody>(body) {
+        Ok(ErrorBody::Api { error }) => OpenAIError::ApiError(error),
+        Ok(ErrorBody::Text { error }) => OpenAIError::StreamError(format!("{}: {}", status, error)),
+        Err(_) => OpenAIError::StreamError(format!("{}: {}", status, body.trim())),
+    }
+}
+
+/// The configured key or the one in the environment
+fn resolve_key(key: &Option<String>, var: &Option<String>, default_var: &str) -> Option<String> {
+    key.clone()
+        .or_else(|| env::var(var.as_deref().unwrap_or(default_var)).ok())
+        .filter(|key| !key.is_empty())
+}
+
+/// Role as every wire format spells it
+/// A message for APIs without function calls, calls and their results are told
+/// as text so the arguments don't pass for an answer
+fn plain_text(message: &Message) -> String {
+    match (&message.role, &message.name) {
+        (Role::Assistant, Some(name)) => format!("[called {} with {}]", name, message.content),
+        (_, Some(name)) => format!("[result of {}]\n{}", name, message.content),
+        _ => message.content.clone(),
+    }
+}
+
+fn role_name(role: &Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    }
+}
+
+fn build(config: &ProviderConfig) -> Result<Arc<dyn Provider>, String> {
+    Ok(match config {
+        ProviderConfig::Ollama { .. } => Arc::new(Ollama::new(config)?),
+        ProviderConfig::Anthropic { .. } => Arc::new(Anthropic::new(config)?),
+        _ => Arc::new(OpenAi::new(config)?),
+    })
+}
+
+/// Named provider profiles from the config
+pub struct Providers {
+    profiles: BTreeMap<String, Arc<dyn Provider>>,
+    // Used by chats that don't pick a profile
+    default: String,
+}
+
+impl Providers {
+    /// Builds every profile, without any the OpenAI API is used with `OPENAI_API_KEY`
+    pub fn new(
+        configs: &BTreeMap<String, ProviderConfig>,
+        default: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut profiles: BTreeMap<String, Arc<dyn Provider>> = BTreeMap::new();
+        for (name, config) in configs {
+            let provider = build(config).map_err(|err| format!("{}: {}", name, err))?;
+            profiles.insert(name.clone(), provider);
+        }
+        if profiles.is_empty() {
+            let config = ProviderConfig::OpenAi {
+                api_key: None,
+                api_key_env: None,
+                base_url: None,
+                model: None,
+            };
+            profiles.insert(DEFAULT_PROFILE.to_string(), build(&config)?);
+        }
+
+        let default = match default {
+            Some(name) if profiles.contains_key(name) => name.to_string(),
+            Some(name) => return Err(format!("unknown default provider {:?}", name)),
+            None => profiles.keys().next().unwrap().clone(),
+        };
+
+        Ok(Self { profiles, default })
+    }
+
+    /// The named profile, None picks the default one
+    pub fn get(&self, name: Option<&str>) -> Option<Arc<dyn Provider>> {
+        self.profiles
+            .get(name.unwrap_or(&self.default))
+            .map(Arc::clone)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+}
+
+/// Feeds a recorded answer to the parser in small chunks like the network would.
+/// Returns the text and how the answer ended, None when it never did.
+#[cfg(test)]
+fn replay<F>(fixture: &str, parse: F) -> (String, Option<Result<(), OpenAIError>>)
+where
+    F: Fn(&str) -> Option<Delta>,
+{
+    let mut lines = LineBuffer::default();
+    let mut text = String::new();
+    for chunk in fixture.as_bytes().chunks(7) {
+        for line in lines.push(chunk) {
+            match parse(&line) {
+                Some(Ok(Some(Part::Text(delta)))) => text += &delta,
+                Some(Ok(None)) => return (text, Some(Ok(()))),
+                Some(Err(err)) => return (text, Some(Err(err))),
+                _ => {}
+            }
+        }
+    }
+    (text, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ProviderConfig;
+    use crate::provider::{sse_data, LineBuffer, Providers};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    // True when both names lead to the same profile
+    fn same(providers: &Providers, a: Option<&str>, b: Option<&str>) -> bool {
+        Arc::ptr_eq(&providers.get(a).unwrap(), &providers.get(b).unwrap())
+    }
+
+    #[test]
+    fn lines() {
+        let mut buffer = LineBuffer::default();
+        assert_eq!(buffer.push(b"data: a"), Vec::<String>::new());
+        assert_eq!(buffer.push(b"b\r\n\ndata: \xc3"), vec!["data: ab", ""]);
+        // The character is only decoded once it is complete
+        assert_eq!(buffer.push(b"\xa9\n"), vec!["data: é"]);
+
+        assert_eq!(sse_data("data: [DONE]"), Some("[DONE]"));
+        assert_eq!(sse_data("data:{}"), Some("{}"));
+        assert_eq!(sse_data(": keep-alive"), None);
+    }
+
+    #[test]
+    fn profiles() {
+        let mut configs = BTreeMap::new();
+        configs.insert(
+            "local".to_string(),
+            ProviderConfig::Local {
+                base_url: "http://localhost:8080/v1".to_string(),
+                api_key: None,
+                model: Some("llama".to_string()),
+            },
+        );
+        configs.insert(
+            "azure".to_string(),
+            ProviderConfig::Azure {
+                endpoint: "https://example.openai.azure.com".to_string(),
+                deployment: "gpt".to_string(),
+                api_version: None,
+                api_key: Some("key".to_string()),
+                api_key_env: None,
+            },
+        );
+
+        let providers = Providers::new(&configs, Some("local")).unwrap();
+        assert_eq!(providers.names(), vec!["azure", "local"]);
+        assert!(same(&providers, None, Some("local")));
+        assert!(!same(&providers, None, Some("azure")));
+        assert!(providers.get(Some("missing")).is_none());
+
+        // The first profile is the default when none is set
+        let providers = Providers::new(&configs, None).unwrap();
+        assert!(same(&providers, None, Some("azure")));
+
+        assert!(Providers::new(&configs, Some("missing")).is_err());
+    }
+}
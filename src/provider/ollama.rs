@@ -0,0 +1,165 @@
+use crate::chat::ModelParams;
+use crate::config::ProviderConfig;
+use crate::provider::{
+    block_on, plain_text, read_lines, role_name, Delta, Part, Provider, DEFAULT_MAX_TOKENS,
+};
+use async_openai::error::OpenAIError;
+use async_openai::types::ChatCompletionRequestMessage as Message;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::mpsc::Sender;
+
+const OLLAMA_URL: &str = "http://localhost:11434";
+
+#[derive(Deserialize)]
+struct Chunk {
+    message: Option<ChunkMessage>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkMessage {
+    content: String,
+}
+
+/// Ollama's native `/api/chat`, answers stream as one JSON object per line
+pub struct Ollama {
+    http: reqwest::Client,
+    url: String,
+    model: String,
+}
+
+/// Reads one line of the answer
+fn parse_line(line: &str) -> Option<Delta> {
+    if line.trim().is_empty() {
+        return None;
+    }
+
+    Some(match serde_json::from_str::<Chunk>(line) {
+        Ok(Chunk {
+            error: Some(error), ..
+        }) => Err(OpenAIError::StreamError(error)),
+        // The last line only holds statistics
+        Ok(Chunk { done: true, .. }) => Ok(None),
+        Ok(chunk) => Ok(Some(Part::Text(
+            chunk.message.map(|m| m.content).unwrap_or_default(),
+        ))),
+        Err(err) => Err(OpenAIError::JSONDeserialize(err)),
+    })
+}
+
+impl Ollama {
+    pub fn new(config: &ProviderConfig) -> Result<Self, String> {
+        let ProviderConfig::Ollama { base_url, model } = config else {
+            return Err("not an ollama profile".to_string());
+        };
+        let url = format!(
+            "{}/api/chat",
+            base_url
+                .as_deref()
+                .unwrap_or(OLLAMA_URL)
+                .trim_end_matches('/')
+        );
+        reqwest::Url::parse(&url).map_err(|err| format!("invalid url {:?}: {}", url, err))?;
+
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url,
+            model: model.clone(),
+        })
+    }
+
+    // System prompts stay in the messages, sampling settings go in the options
+    fn body(&self, messages: &[Message], params: &ModelParams) -> Value {
+        let messages: Vec<Value> = messages
+            .iter()
+            .map(|m| json!({ "role": role_name(&m.role), "content": plain_text(m) }))
+            .collect();
+        let mut options = json!({
+            "num_predict": params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        });
+        if let Some(temperature) = params.temperature {
+            options["temperature"] = json!(temperature);
+        }
+
+        json!({
+            "model": params.model.as_deref().unwrap_or(&self.model),
+            "messages": messages,
+            "stream": true,
+            "options": options,
+        })
+    }
+}
+
+impl Provider for Ollama {
+    fn stream(&self, messages: Vec<Message>, params: &ModelParams, tx: &Sender<Delta>) {
+        let request = self
+            .http
+            .post(&self.url)
+            .json(&self.body(&messages, params));
+        block_on(tx, async {
+            let result = read_lines(request, tx, parse_line).await;
+            let _ = tx.send(result.map(|_| None));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::{call_msg, result_msg, system_msg, user_msg, ModelParams};
+    use crate::config::ProviderConfig;
+    use crate::provider::ollama::{parse_line, Ollama};
+    use crate::provider::replay;
+    use serde_json::json;
+
+    #[test]
+    fn fixtures() {
+        let (text, end) = replay(include_str!("fixtures/ollama_chat.ndjson"), parse_line);
+        assert_eq!(text, "The sky is blue, ünless it's night.");
+        assert!(matches!(end, Some(Ok(()))));
+
+        let (text, end) = replay(include_str!("fixtures/ollama_error.ndjson"), parse_line);
+        assert_eq!(text, "The");
+        assert_eq!(
+            end.unwrap().unwrap_err().to_string(),
+            "stream failed: model runner has unexpectedly stopped"
+        );
+    }
+
+    #[test]
+    fn body() {
+        let ollama = Ollama::new(&ProviderConfig::Ollama {
+            base_url: None,
+            model: "llama3".to_string(),
+        })
+        .unwrap();
+        assert_eq!(ollama.url, "http://localhost:11434/api/chat");
+
+        let params = ModelParams {
+            temperature: Some(0.5),
+            ..ModelParams::default()
+        };
+        let messages = [
+            system_msg("Be brief".to_string()),
+            user_msg("Hi".to_string()),
+            call_msg("list_dir".to_string(), r#"{"path":"."}"#.to_string()),
+            result_msg("list_dir".to_string(), "notes.md".to_string()),
+        ];
+        assert_eq!(
+            ollama.body(&messages, &params),
+            json!({
+                "model": "llama3",
+                "messages": [
+                    { "role": "system", "content": "Be brief" },
+                    { "role": "user", "content": "Hi" },
+                    { "role": "assistant", "content": r#"[called list_dir with {"path":"."}]"# },
+                    { "role": "user", "content": "[result of list_dir]\nnotes.md" },
+                ],
+                "stream": true,
+                "options": { "num_predict": 500, "temperature": 0.5 },
+            })
+        );
+    }
+}
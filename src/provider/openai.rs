@@ -0,0 +1,416 @@
+use crate::chat::ModelParams;
+use crate::config::ProviderConfig;
+use crate::provider::{
+    block_on, read_lines, resolve_key, role_name, sse_data, Delta, Part, Provider,
+    DEFAULT_MAX_TOKENS, DEFAULT_MODEL,
+};
+use crate::tools;
+use async_openai::error::{ApiError, OpenAIError};
+use async_openai::types::{
+    ChatCompletionRequestMessage as Message, CreateChatCompletionRequestArgs as ChatModel, Role,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::mpsc::Sender;
+
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+const AZURE_API_VERSION: &str = "2023-05-15";
+
+// How the key is sent
+#[derive(Debug, PartialEq)]
+enum Auth {
+    Bearer,
+    // Azure takes it in its own header
+    ApiKey,
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    // Azure starts with a chunk without choices
+    #[serde(default)]
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    delta: ChoiceDelta,
+}
+
+#[derive(Deserialize)]
+struct ChoiceDelta {
+    content: Option<String>,
+    function_call: Option<FunctionDelta>,
+}
+
+// The name comes in the first piece of a call, the arguments in every piece
+#[derive(Deserialize)]
+struct FunctionDelta {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorEvent {
+    error: ApiError,
+}
+
+/// Any service speaking the OpenAI chat completions API: OpenAI, Azure OpenAI
+/// and local servers like llama.cpp, vLLM or Ollama's `/v1`
+pub struct OpenAi {
+    http: reqwest::Client,
+    url: String,
+    auth: Auth,
+    // Err says why there is no key, a missing key only fails the requests
+    key: Result<String, String>,
+    // Used when the chat doesn't set one
+    model: String,
+}
+
+/// Reads one event, text is empty when the chunk had none and None ends the answer
+fn parse_data(data: &str) -> Delta {
+    if data == "[DONE]" {
+        return Ok(None);
+    }
+    if let Ok(event) = serde_json::from_str::<ErrorEvent>(data) {
+        return Err(OpenAIError::ApiError(event.error));
+    }
+
+    let chunk: Chunk = serde_json::from_str(data).map_err(OpenAIError::JSONDeserialize)?;
+    let mut text = String::new();
+    for choice in chunk.choices {
+        if let Some(call) = choice.delta.function_call {
+            return Ok(Some(Part::Call(call.name, call.arguments)));
+        }
+        text.extend(choice.delta.content);
+    }
+    Ok(Some(Part::Text(text)))
+}
+
+/// A message as the API takes it, function calls and their results are the
+/// assistant and user messages with a name
+fn wire(message: &Message) -> Value {
+    match (&message.role, &message.name) {
+        (Role::Assistant, Some(name)) => json!({
+            "role": "assistant",
+            "content": null,
+            "function_call": { "name": name, "arguments": message.content },
+        }),
+        (Role::User, Some(name)) => json!({
+            "role": "function",
+            "name": name,
+            "content": message.content,
+        }),
+        (role, _) => json!({ "role": role_name(role), "content": message.content }),
+    }
+}
+
+impl OpenAi {
+    pub fn new(config: &ProviderConfig) -> Result<Self, String> {
+        let (url, auth, key, model) = match config {
+            ProviderConfig::OpenAi {
+                api_key,
+                api_key_env,
+                base_url,
+                model,
+            } => (
+                format!(
+                    "{}/chat/completions",
+                    base_url
+                        .as_deref()
+                        .unwrap_or(OPENAI_URL)
+                        .trim_end_matches('/')
+                ),
+                Auth::Bearer,
+                resolve_key(api_key, api_key_env, "OPENAI_API_KEY")
+                    .ok_or("no API key, set api_key in the provider or OPENAI_API_KEY".to_string()),
+                model.clone(),
+            ),
+            ProviderConfig::Azure {
+                endpoint,
+                deployment,
+                api_version,
+                api_key,
+                api_key_env,
+            } => (
+                format!(
+                    "{}/openai/deployments/{}/chat/completions?api-version={}",
+                    endpoint.trim_end_matches('/'),
+                    deployment,
+                    api_version.as_deref().unwrap_or(AZURE_API_VERSION)
+                ),
+                Auth::ApiKey,
+                resolve_key(api_key, api_key_env, "AZURE_OPENAI_API_KEY").ok_or(
+                    "no API key, set api_key in the provider or AZURE_OPENAI_API_KEY".to_string(),
+                ),
+                // The deployment picks the model
+                Some(deployment.clone()),
+            ),
+            // Local servers rarely check keys
+            ProviderConfig::Local {
+                base_url,
+                api_key,
+                model,
+            } => (
+                format!("{}/chat/completions", base_url.trim_end_matches('/')),
+                Auth::Bearer,
+                Ok(api_key.clone().unwrap_or_default()),
+                model.clone(),
+            ),
+            _ => return Err("not an OpenAI compatible profile".to_string()),
+        };
+
+        reqwest::Url::parse(&url).map_err(|err| format!("invalid url {:?}: {}", url, err))?;
+
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url,
+            auth,
+            key,
+            model: model.unwrap_or(DEFAULT_MODEL.to_string()),
+        })
+    }
+
+    async fn send(
+        &self,
+        messages: Vec<Message>,
+        params: &ModelParams,
+        tx: &Sender<Delta>,
+    ) -> Result<(), OpenAIError> {
+        let key = self.key.clone().map_err(OpenAIError::InvalidArgument)?;
+
+        let mut request = ChatModel::default();
+        request
+            .max_tokens(params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS))
+            .model(params.model.as_deref().unwrap_or(&self.model))
+            .messages(vec![])
+            .stream(true);
+        if let Some(temperature) = params.temperature {
+            request.temperature(temperature);
+        }
+        let mut body =
+            serde_json::to_value(request.build()?).map_err(OpenAIError::JSONDeserialize)?;
+        // The library doesn't know about function calls
+        body["messages"] = messages.iter().map(wire).collect();
+        if params.tools {
+            body["functions"] = tools::schemas();
+        }
+
+        let mut builder = self.http.post(&self.url).json(&body);
+        if !key.is_empty() {
+            builder = match self.auth {
+                Auth::Bearer => builder.bearer_auth(key),
+                Auth::ApiKey => builder.header("api-key", key),
+            };
+        }
+
+        read_lines(builder, tx, |line| sse_data(line).map(parse_data)).await
+    }
+}
+
+impl Provider for OpenAi {
+    fn stream(&self, messages: Vec<Message>, params: &ModelParams, tx: &Sender<Delta>) {
+        block_on(tx, async {
+            let result = self.send(messages, params, tx).await;
+            // Errors end the answer too
+            let _ = tx.send(result.map(|_| None));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::{call_msg, result_msg, system_msg, user_msg, ModelParams};
+    use crate::config::ProviderConfig;
+    use crate::provider::mock::{answer, chunk, End, MockServer, Reply};
+    use crate::provider::openai::{parse_data, wire, Auth, OpenAi};
+    use crate::provider::{Part, Provider};
+    use async_openai::error::OpenAIError;
+    use async_openai::types::CreateChatCompletionStreamResponse;
+    use serde_json::json;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn urls() {
+        let azure = OpenAi::new(&ProviderConfig::Azure {
+            endpoint: "https://example.openai.azure.com".to_string(),
+            deployment: "gpt35".to_string(),
+            api_version: None,
+            api_key: Some("key".to_string()),
+            api_key_env: None,
+        })
+        .unwrap();
+        assert_eq!(
+            azure.url,
+            "https://example.openai.azure.com/openai/deployments/gpt35/chat/completions?api-version=2023-05-15"
+        );
+        assert_eq!(azure.auth, Auth::ApiKey);
+        assert_eq!(azure.model, "gpt35");
+
+        // Trailing slashes are fine
+        let local = OpenAi::new(&ProviderConfig::Local {
+            base_url: "http://localhost:11434/v1/".to_string(),
+            api_key: None,
+            model: None,
+        })
+        .unwrap();
+        assert_eq!(local.url, "http://localhost:11434/v1/chat/completions");
+        assert_eq!(local.key, Ok(String::new()));
+
+        let missing = OpenAi::new(&ProviderConfig::OpenAi {
+            api_key: None,
+            api_key_env: Some("GPTERM_TEST_MISSING_KEY".to_string()),
+            base_url: None,
+            model: None,
+        })
+        .unwrap();
+        assert!(missing.key.is_err());
+
+        assert!(OpenAi::new(&ProviderConfig::Local {
+            base_url: "localhost".to_string(),
+            api_key: None,
+            model: None,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn chunks() {
+        let chunk = r#"{"id":"1","object":"chat.completion.chunk","created":0,"model":"gpt","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#;
+        assert_eq!(
+            parse_data(chunk).unwrap(),
+            Some(Part::Text("Hi".to_string()))
+        );
+        // Role only and filter result chunks have no text
+        assert_eq!(
+            parse_data(r#"{"choices":[{"delta":{"role":"assistant"}}]}"#).unwrap(),
+            Some(Part::Text(String::new()))
+        );
+        assert_eq!(
+            parse_data(r#"{"choices":[],"prompt_filter_results":[]}"#).unwrap(),
+            Some(Part::Text(String::new()))
+        );
+        let call = r#"{"choices":[{"delta":{"content":null,"function_call":{"name":"grep","arguments":""}}}]}"#;
+        assert_eq!(
+            parse_data(call).unwrap(),
+            Some(Part::Call("grep".to_string(), String::new()))
+        );
+        let call = r#"{"choices":[{"delta":{"function_call":{"arguments":"{\"pat"}}}]}"#;
+        assert_eq!(
+            parse_data(call).unwrap(),
+            Some(Part::Call(String::new(), "{\"pat".to_string()))
+        );
+        assert_eq!(parse_data("[DONE]").unwrap(), None);
+
+        let error =
+            r#"{"error":{"message":"Slow down","type":"rate_limit","param":null,"code":null}}"#;
+        assert!(
+            matches!(parse_data(error), Err(OpenAIError::ApiError(e)) if e.message == "Slow down")
+        );
+        assert!(matches!(
+            parse_data("{not json"),
+            Err(OpenAIError::JSONDeserialize(_))
+        ));
+    }
+
+    #[test]
+    fn stream() {
+        // The mock chunks are real stream responses
+        serde_json::from_value::<CreateChatCompletionStreamResponse>(chunk("Hi")).unwrap();
+
+        let server = MockServer::start(vec![
+            Reply::stream(&["Hel", "lo ", "wörld"], End::Done),
+            Reply::stream(&["Hel"], End::Disconnect),
+            Reply::stream(&["Hel"], End::Error("Slow down")),
+            Reply::error(401, "Bad key"),
+        ]);
+        let openai = OpenAi::new(&ProviderConfig::Local {
+            base_url: server.url(),
+            api_key: Some("key".to_string()),
+            model: None,
+        })
+        .unwrap();
+        let messages = vec![
+            system_msg("Be brief".to_string()),
+            user_msg("Hi".to_string()),
+        ];
+
+        let (text, end) = answer(&openai, messages.clone());
+        assert_eq!(text, "Hello wörld");
+        assert!(end.is_ok());
+        let request = server.request();
+        assert_eq!(request["model"], "gpt-3.5-turbo");
+        assert_eq!(request["stream"], true);
+        assert_eq!(
+            request["messages"],
+            json!([
+                { "role": "system", "content": "Be brief" },
+                { "role": "user", "content": "Hi" },
+            ])
+        );
+
+        let (text, end) = answer(&openai, messages.clone());
+        assert_eq!(text, "Hel");
+        assert!(matches!(end, Err(OpenAIError::StreamError(_))));
+
+        let (text, end) = answer(&openai, messages.clone());
+        assert_eq!(text, "Hel");
+        assert!(matches!(end, Err(OpenAIError::ApiError(e)) if e.message == "Slow down"));
+
+        let (text, end) = answer(&openai, messages);
+        assert_eq!(text, "");
+        assert!(matches!(end, Err(OpenAIError::ApiError(e)) if e.message == "Bad key"));
+    }
+
+    #[test]
+    fn function_calls() {
+        let call = call_msg("grep".to_string(), r#"{"pattern":"main"}"#.to_string());
+        assert_eq!(
+            wire(&call),
+            json!({
+                "role": "assistant",
+                "content": null,
+                "function_call": { "name": "grep", "arguments": r#"{"pattern":"main"}"# },
+            })
+        );
+        let result = result_msg("grep".to_string(), "src/main.rs:1:fn main()".to_string());
+        assert_eq!(
+            wire(&result),
+            json!({ "role": "function", "name": "grep", "content": "src/main.rs:1:fn main()" })
+        );
+
+        let server = MockServer::start(vec![
+            Reply::call("list_dir", &[r#"{"path""#, r#":"."}"#]),
+            Reply::stream(&["Hi"], End::Done),
+        ]);
+        let openai = OpenAi::new(&ProviderConfig::Local {
+            base_url: server.url(),
+            api_key: None,
+            model: None,
+        })
+        .unwrap();
+        let params = ModelParams {
+            tools: true,
+            ..ModelParams::default()
+        };
+        let (tx, rx) = channel();
+        openai.stream(vec![user_msg("Hi".to_string())], &params, &tx);
+        let parts: Vec<_> = rx.try_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            parts,
+            [
+                Some(Part::Call("list_dir".to_string(), String::new())),
+                Some(Part::Call(String::new(), r#"{"path""#.to_string())),
+                Some(Part::Call(String::new(), r#":"."}"#.to_string())),
+                None,
+            ]
+        );
+        assert_eq!(server.request()["functions"][0]["name"], "read_file");
+
+        // Functions are only offered when tools are on
+        let (text, _) = answer(&openai, vec![user_msg("Hi".to_string())]);
+        assert_eq!(text, "Hi");
+        assert!(server.request().get("functions").is_none());
+    }
+}
@@ -1,17 +1,14 @@
+use crate::clipboard::Clipboard;
+use crate::keymap::{keymap, Action, Context};
 use crate::Window;
-use arboard::Clipboard;
-use crossterm::event::{KeyCode, KeyEvent};
-use tui::{backend::Backend, Frame};
+use crossterm::event::KeyEvent;
 
 pub struct Settings {}
 
 impl Window for Settings {
     type InputReturn = bool;
 
-    fn input(&mut self, key: &KeyEvent, clipboard: &mut Clipboard) -> Self::InputReturn {
-        match key.code {
-            KeyCode::Esc => true,
-            _ => false,
-        }
+    fn input(&mut self, key: &KeyEvent, _clipboard: &mut dyn Clipboard) -> Self::InputReturn {
+        keymap().action(Context::Settings, key) == Some(Action::Cancel)
     }
 }
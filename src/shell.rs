@@ -0,0 +1,189 @@
+// Local commands whose output goes into a prompt, written as `!command`, `!!` sends a `!`
+
+use crate::attach::fence;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Commands still running after this are killed
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+/// Output kept from a command, the rest is dropped
+pub const LIMIT: usize = 32 * 1024;
+
+/// What a command printed, stdout and stderr mixed as they came
+#[derive(Debug, PartialEq)]
+pub struct Output {
+    pub text: String,
+    // None when the command was killed
+    pub code: Option<i32>,
+    pub truncated: bool,
+    pub timed_out: bool,
+    // What the command ran with, told in the block
+    pub timeout: Duration,
+    pub limit: usize,
+}
+
+/// The command written in a prompt starting with `!`, `!!` sends a `!` instead
+pub fn command(prompt: &str) -> Option<&str> {
+    let command = prompt.trim().strip_prefix('!')?;
+    if command.starts_with('!') {
+        return None;
+    }
+    let command = command.trim();
+    (!command.is_empty()).then_some(command)
+}
+
+/// Runs the command with `sh`, killing it and everything it started after the timeout
+pub fn run(command: &str, timeout: Duration, limit: usize) -> io::Result<Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        // The braces keep redirections written in the command working
+        .arg(format!("{{ {}\n}} 2>&1", command))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // Own process group so the whole group can be killed
+        .process_group(0)
+        .spawn()?;
+
+    let mut stdout = child.stdout.take().unwrap();
+    let (send, recv) = channel();
+    thread::spawn(move || {
+        let mut kept = vec![];
+        let mut total = 0;
+        let mut buffer = [0; 4096];
+        // Keep reading past the limit so the command doesn't block on a full pipe
+        while let Ok(n) = stdout.read(&mut buffer) {
+            if n == 0 {
+                break;
+            }
+            let room = limit.saturating_sub(kept.len());
+            kept.extend_from_slice(&buffer[..n.min(room)]);
+            total += n;
+        }
+        let _ = send.send((kept, total));
+    });
+
+    let start = Instant::now();
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            timed_out = true;
+            let _ = Command::new("kill")
+                .args(["-KILL", "--", &format!("-{}", child.id())])
+                .status();
+            let _ = child.kill();
+            break child.wait()?;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // Something started in the background may hold the pipe open, don't wait for it
+    let (kept, total) = recv
+        .recv_timeout(Duration::from_millis(500))
+        .unwrap_or_default();
+    Ok(Output {
+        text: String::from_utf8_lossy(&kept).into_owned(),
+        code: status.code(),
+        truncated: total > kept.len(),
+        timed_out,
+        timeout,
+        limit,
+    })
+}
+
+/// The command and its output as a fenced block for the prompt
+pub fn block(command: &str, output: &Output) -> String {
+    let mut text = output.text.trim_end().to_string();
+    if output.truncated {
+        text += &format!("\n[output cut at {}]", size(output.limit));
+    }
+    let status = match (output.timed_out, output.code) {
+        (true, _) => format!("killed after {}", duration(output.timeout)),
+        (false, Some(code)) => format!("exit code {}", code),
+        (false, None) => "killed by a signal".to_string(),
+    };
+    let fence = fence(&text);
+    format!(
+        "{}console\n$ {}\n{}\n{}\n[{}]",
+        fence, command, text, fence, status
+    )
+}
+
+fn size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} bytes", bytes),
+        _ => format!("{} KB", bytes / 1024),
+    }
+}
+
+fn duration(time: Duration) -> String {
+    match time.as_secs() {
+        0 => format!("{} ms", time.as_millis()),
+        1 => "1 second".to_string(),
+        secs => format!("{} seconds", secs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::{block, command, run, Output, LIMIT, TIMEOUT};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn commands() {
+        assert_eq!(command("!cargo test 2>&1"), Some("cargo test 2>&1"));
+        assert_eq!(command("  ! ls "), Some("ls"));
+        assert_eq!(command("!"), None);
+        assert_eq!(command("hi !ls"), None);
+        assert_eq!(command("!!important"), None);
+    }
+
+    #[test]
+    fn output() {
+        let output = run(
+            "echo out; echo err >&2; exit 3",
+            Duration::from_secs(5),
+            100,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            Output {
+                text: "out\nerr\n".to_string(),
+                code: Some(3),
+                truncated: false,
+                timed_out: false,
+                timeout: Duration::from_secs(5),
+                limit: 100,
+            }
+        );
+        assert_eq!(
+            block("make", &output),
+            "```console\n$ make\nout\nerr\n```\n[exit code 3]"
+        );
+
+        let output = run("yes | head -c 1000", Duration::from_secs(5), 10).unwrap();
+        assert_eq!(output.text.len(), 10);
+        assert!(output.truncated);
+        assert!(block("yes", &output).contains("[output cut at 10 bytes]"));
+        let output = run("yes | head -c 40000", TIMEOUT, LIMIT).unwrap();
+        assert!(block("yes", &output).contains("[output cut at 32 KB]"));
+    }
+
+    #[test]
+    fn timeout() {
+        let start = Instant::now();
+        let output = run("echo started; sleep 10", Duration::from_millis(200), 100).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(output.timed_out);
+        assert_eq!(output.text, "started\n");
+        assert!(block("sleep", &output).ends_with("[killed after 200 ms]"));
+    }
+}
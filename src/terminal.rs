@@ -0,0 +1,226 @@
+// Keeps the user's terminal usable however the app ends: normal quits, panics
+// and signals all leave raw mode and the alternate screen
+
+use crate::config;
+use crossterm::{
+    cursor::Show,
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use signal_hook::consts::{SIGHUP, SIGTERM};
+use std::backtrace::Backtrace;
+use std::env;
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// True while the terminal is in raw mode on the alternate screen
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Sets up the terminal for the app and puts it back when dropped
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<Self> {
+        activate()?;
+        Ok(Self)
+    }
+}
+
+fn activate() -> io::Result<()> {
+    enable_raw_mode()?;
+    ACTIVE.store(true, Ordering::SeqCst);
+    execute!(
+        io::stdout(),
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leaves the app's terminal modes, only the first call does anything
+pub fn restore() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste,
+            Show
+        );
+    }
+}
+
+/// Opens `$VISUAL` or `$EDITOR` on a temporary file holding the text and returns it
+/// as saved. The app's terminal modes are left while the editor runs, the screen
+/// has to be redrawn in full afterwards.
+pub fn edit(text: &str) -> io::Result<String> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let active = ACTIVE.load(Ordering::SeqCst);
+    restore();
+    let result = edit_with(&editor, text);
+    if active {
+        activate()?;
+    }
+    result
+}
+
+// Runs the editor command through the shell so it can carry arguments, like `code --wait`
+fn edit_with(editor: &str, text: &str) -> io::Result<String> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_nanos());
+    let path = env::temp_dir().join(format!("gpterm-{}-{}.md", process::id(), time));
+    fs::write(&path, text)?;
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    let edited = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            editor, status
+        ))),
+        Err(err) => Err(err),
+    };
+    let _ = fs::remove_file(&path);
+
+    // Editors end the file with a line break the text didn't have
+    let mut edited = edited?;
+    if edited.ends_with('\n') && !text.ends_with('\n') {
+        edited.pop();
+    }
+    Ok(edited)
+}
+
+/// Set once SIGTERM or SIGHUP arrives, the app should then save and quit.
+/// A second signal exits right away in case the app is stuck.
+pub fn signals() -> io::Result<Arc<AtomicBool>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGHUP] {
+        // Checked before the flag is set, so only a second signal exits
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&stop))?;
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+    Ok(stop)
+}
+
+/// Restores the terminal and writes a crash report before the panic message is printed.
+/// Panics in the request threads leave the screen alone, the app keeps running.
+pub fn install_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() == Some("main") {
+            restore();
+            match report(info) {
+                Ok(path) => eprintln!("gpterm: crash report written to {}", path.display()),
+                Err(err) => eprintln!("gpterm: could not write the crash report: {}", err),
+            }
+        }
+        default(info);
+    }));
+}
+
+fn report(info: &PanicHookInfo) -> io::Result<PathBuf> {
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match info.payload().downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_string(),
+        },
+    };
+    let location = info
+        .location()
+        .map_or("unknown location".to_string(), |l| l.to_string());
+    let text = format!(
+        "gpterm {} panicked at {}:\n{}\n\n{}",
+        env!("CARGO_PKG_VERSION"),
+        location,
+        message,
+        Backtrace::force_capture()
+    );
+    save("crash", "txt", &text)
+}
+
+/// Writes a file named after the kind and the time into the state directory
+pub fn save(kind: &str, extension: &str, text: &str) -> io::Result<PathBuf> {
+    let dir = config::state_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory"))?;
+    save_in(&dir, kind, extension, text)
+}
+
+fn save_in(dir: &Path, kind: &str, extension: &str, text: &str) -> io::Result<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_secs());
+    fs::create_dir_all(dir)?;
+
+    // Never overwrite an older file from the same second
+    let mut path = dir.join(format!("{}-{}.{}", kind, time, extension));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}-{}.{}", kind, time, n, extension));
+        n += 1;
+    }
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::terminal::{edit_with, save_in};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn save() {
+        let dir = env::temp_dir().join(format!("gpterm-save-{}", std::process::id()));
+        let first = save_in(&dir, "crash", "txt", "one").unwrap();
+        let second = save_in(&dir, "crash", "txt", "two").unwrap();
+
+        assert_ne!(first, second);
+        assert!(first
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("crash-"));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "one");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "two");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn edit() {
+        assert_eq!(
+            edit_with("sed -i s/one/two/", "one\nthree").unwrap(),
+            "two\nthree"
+        );
+        // The line break editors add is dropped, the ones written are kept
+        assert_eq!(edit_with("sed -i s/a/b/", "a").unwrap(), "b");
+        assert_eq!(edit_with("true", "a\n").unwrap(), "a\n");
+        assert!(edit_with("false", "a").is_err());
+    }
+}
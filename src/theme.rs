@@ -0,0 +1,324 @@
+use crate::config::{self, ConfigError};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+use tui::style::{Color, Modifier, Style};
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+// Style names used by theme files
+const SLOTS: [&str; 12] = [
+    "user",
+    "system",
+    "assistant",
+    "tab_highlight",
+    "input",
+    "input_loading",
+    "unfocused",
+    "find_match",
+    "find_current",
+    "status",
+    "heading",
+    "tool",
+];
+
+/// Named styles used by every window
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    // Message senders
+    pub user: Style,
+    pub system: Style,
+    pub assistant: Style,
+    // Selected chat tab
+    pub tab_highlight: Style,
+    // Input box while writing and while waiting for an answer
+    pub input: Style,
+    pub input_loading: Style,
+    // Fields that don't have focus
+    pub unfocused: Style,
+    pub find_match: Style,
+    pub find_current: Style,
+    pub status: Style,
+    pub heading: Style,
+    // Function calls and their results
+    pub tool: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            user: Style::default().fg(Color::Green),
+            system: Style::default().fg(Color::Yellow),
+            assistant: Style::default().fg(Color::Blue),
+            tab_highlight: Style::default()
+                .add_modifier(Modifier::BOLD)
+                .bg(Color::DarkGray),
+            input: Style::default().fg(Color::Blue),
+            input_loading: Style::default().fg(Color::Red),
+            unfocused: Style::default().fg(Color::DarkGray),
+            find_match: Style::default().fg(Color::Black).bg(Color::Yellow),
+            find_current: Style::default().fg(Color::Black).bg(Color::LightRed),
+            status: Style::default().fg(Color::DarkGray),
+            heading: Style::default().add_modifier(Modifier::BOLD),
+            tool: Style::default().fg(Color::Cyan),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            user: Style::default().fg(Color::Green),
+            system: Style::default().fg(Color::Magenta),
+            assistant: Style::default().fg(Color::Blue),
+            tab_highlight: Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(Color::Black)
+                .bg(Color::Gray),
+            input: Style::default().fg(Color::Blue),
+            input_loading: Style::default().fg(Color::Red),
+            // Gray text fades into light backgrounds, dark gray stays readable
+            unfocused: Style::default().fg(Color::DarkGray),
+            find_match: Style::default().fg(Color::Black).bg(Color::LightYellow),
+            find_current: Style::default().fg(Color::Black).bg(Color::LightRed),
+            status: Style::default().fg(Color::DarkGray),
+            heading: Style::default().add_modifier(Modifier::BOLD),
+            tool: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Self {
+            user: bold.fg(Color::LightGreen),
+            system: bold.fg(Color::LightYellow),
+            assistant: bold.fg(Color::LightCyan),
+            tab_highlight: bold.add_modifier(Modifier::REVERSED),
+            input: bold.fg(Color::White),
+            input_loading: bold.fg(Color::LightRed),
+            unfocused: Style::default().fg(Color::Gray),
+            find_match: bold.fg(Color::Black).bg(Color::LightYellow),
+            find_current: bold.fg(Color::Black).bg(Color::LightMagenta),
+            status: Style::default().fg(Color::White),
+            heading: bold.add_modifier(Modifier::UNDERLINED),
+            tool: bold.fg(Color::LightMagenta),
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// Finds a built in theme or loads `themes/<name>.toml` from the config directory
+    pub fn load(name: &str) -> Result<Self, ConfigError> {
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+
+        let path = config::dir()
+            .map(|dir| dir.join("themes").join(format!("{}.toml", name)))
+            .filter(|path| path.exists())
+            .ok_or(ConfigError::Theme(format!("unknown theme {:?}", name)))?;
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let file: ThemeFile = toml::from_str(&text).map_err(ConfigError::Parse)?;
+        file.build().map_err(ConfigError::Theme)
+    }
+
+    fn slot(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "user" => &mut self.user,
+            "system" => &mut self.system,
+            "assistant" => &mut self.assistant,
+            "tab_highlight" => &mut self.tab_highlight,
+            "input" => &mut self.input,
+            "input_loading" => &mut self.input_loading,
+            "unfocused" => &mut self.unfocused,
+            "find_match" => &mut self.find_match,
+            "find_current" => &mut self.find_current,
+            "status" => &mut self.status,
+            "heading" => &mut self.heading,
+            "tool" => &mut self.tool,
+            _ => return None,
+        })
+    }
+
+    /// Drops every color, highlights are kept visible by reversing them
+    pub fn no_color(mut self) -> Self {
+        for name in SLOTS {
+            let style = self.slot(name).unwrap();
+            let mut plain = Style::default()
+                .add_modifier(style.add_modifier)
+                .remove_modifier(style.sub_modifier);
+            if style.bg.is_some() {
+                plain = plain.add_modifier(Modifier::REVERSED);
+            }
+            *style = plain;
+        }
+        self
+    }
+}
+
+/// A user theme, slots that are left out come from the base theme
+#[derive(Deserialize)]
+struct ThemeFile {
+    base: Option<String>,
+    #[serde(flatten)]
+    slots: HashMap<String, StyleFile>,
+}
+
+#[derive(Deserialize)]
+struct StyleFile {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+impl ThemeFile {
+    fn build(self) -> Result<Theme, String> {
+        let base = self.base.as_deref().unwrap_or("dark");
+        let mut theme = Theme::builtin(base).ok_or(format!("unknown base theme {:?}", base))?;
+
+        for (name, file) in self.slots {
+            let style = theme
+                .slot(&name)
+                .ok_or(format!("unknown style {:?}", name))?;
+            let mut new = Style::default();
+            if let Some(fg) = file.fg {
+                new = new.fg(parse_color(&fg)?);
+            }
+            if let Some(bg) = file.bg {
+                new = new.bg(parse_color(&bg)?);
+            }
+            for modifier in file.modifiers {
+                new = new.add_modifier(parse_modifier(&modifier)?);
+            }
+            *style = new;
+        }
+
+        Ok(theme)
+    }
+}
+
+/// Parses color names, `#rrggbb` and 256 color indexes
+fn parse_color(s: &str) -> Result<Color, String> {
+    if let Some(hex) = s.strip_prefix('#') {
+        return match u32::from_str_radix(hex, 16) {
+            Ok(value) if hex.len() == 6 => Ok(Color::Rgb(
+                (value >> 16) as u8,
+                (value >> 8) as u8,
+                value as u8,
+            )),
+            _ => Err(format!("invalid color {:?}", s)),
+        };
+    }
+    if let Ok(index) = s.parse() {
+        return Ok(Color::Indexed(index));
+    }
+
+    let name = s.to_lowercase().replace(['_', '-', ' '], "");
+    Ok(match name.as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return Err(format!("invalid color {:?}", s)),
+    })
+}
+
+fn parse_modifier(s: &str) -> Result<Modifier, String> {
+    Ok(match s.to_lowercase().as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" => Modifier::UNDERLINED,
+        "reversed" => Modifier::REVERSED,
+        "crossed_out" => Modifier::CROSSED_OUT,
+        _ => return Err(format!("invalid modifier {:?}", s)),
+    })
+}
+
+/// Sets the theme used by every window, can only be done once
+pub fn init(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::theme::{parse_color, Theme, ThemeFile};
+    use tui::style::{Color, Modifier, Style};
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("Light_Red"), Ok(Color::LightRed));
+        assert_eq!(parse_color("#ff8000"), Ok(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color("208"), Ok(Color::Indexed(208)));
+        assert!(parse_color("#ff80").is_err());
+        assert!(parse_color("purple").is_err());
+    }
+
+    #[test]
+    fn user_theme() {
+        let file: ThemeFile = toml::from_str(
+            r##"
+            base = "light"
+            user = { fg = "#010203", modifiers = ["bold"] }
+            "##,
+        )
+        .unwrap();
+        let theme = file.build().unwrap();
+        assert_eq!(
+            theme.user,
+            Style::default()
+                .fg(Color::Rgb(1, 2, 3))
+                .add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(theme.assistant, Theme::light().assistant);
+
+        let file: ThemeFile = toml::from_str("shadow = { fg = \"red\" }").unwrap();
+        assert!(file.build().is_err());
+    }
+
+    #[test]
+    fn no_color() {
+        let theme = Theme::dark().no_color();
+        assert_eq!(theme.user, Style::default());
+        assert_eq!(
+            theme.find_match,
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+        assert_eq!(
+            theme.tab_highlight,
+            Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        );
+    }
+}
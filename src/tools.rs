@@ -0,0 +1,328 @@
+// Built in functions the model can call, every call is confirmed before it runs
+
+use crate::attach::resolve;
+use crate::shell;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::Path;
+
+// Most text a tool gives back, the rest is cut
+const LIMIT: usize = shell::LIMIT;
+// Files larger than this aren't read or searched
+const MAX_FILE: u64 = 4 * 1024 * 1024;
+// Matches listed by a search
+const MAX_MATCHES: usize = 200;
+// Files a search looks at before giving up
+const MAX_FILES: usize = 10_000;
+
+/// The functions as the API describes them
+pub fn schemas() -> Value {
+    json!([
+        {
+            "name": "read_file",
+            "description": "Read a text file, or some of its lines",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string" },
+                    "start_line": { "type": "integer", "description": "First line, from 1" },
+                    "end_line": { "type": "integer", "description": "Last line" },
+                },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "list_dir",
+            "description": "List the files in a directory, directories end with /",
+            "parameters": {
+                "type": "object",
+                "properties": { "path": { "type": "string" } },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "grep",
+            "description": "Find the lines containing some text in the files under a path",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "pattern": { "type": "string", "description": "Text to find, not a regex" },
+                    "path": { "type": "string", "description": "File or directory, . by default" },
+                },
+                "required": ["pattern"],
+            },
+        },
+        {
+            "name": "shell",
+            "description": "Run a command with sh in the current directory",
+            "parameters": {
+                "type": "object",
+                "properties": { "command": { "type": "string" } },
+                "required": ["command"],
+            },
+        },
+    ])
+}
+
+// The arguments object, models sometimes send nothing for no arguments
+fn arguments(arguments: &str) -> Result<Map<String, Value>, String> {
+    if arguments.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match serde_json::from_str(arguments) {
+        Ok(Value::Object(map)) => Ok(map),
+        _ => Err(format!("the arguments aren't a JSON object: {}", arguments)),
+    }
+}
+
+fn string<'a>(args: &'a Map<String, Value>, name: &str) -> Result<&'a str, String> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or(format!("missing the {} argument", name))
+}
+
+fn line(args: &Map<String, Value>, name: &str) -> Option<usize> {
+    args.get(name).and_then(Value::as_u64).map(|n| n as usize)
+}
+
+// Keeps the start of long output
+fn cut(mut text: String) -> String {
+    if text.len() > LIMIT {
+        let mut end = LIMIT;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text += &format!("\n[output cut at {} KB]", LIMIT / 1024);
+    }
+    text
+}
+
+// Reads a whole text file, None for binary files
+fn read_text(path: &Path) -> Result<Option<String>, String> {
+    let error = |err: std::io::Error| format!("{}: {}", path.display(), err);
+    if fs::metadata(path).map_err(error)?.len() > MAX_FILE {
+        return Err(format!("{} is too large", path.display()));
+    }
+    let bytes = fs::read(path).map_err(error)?;
+    Ok(String::from_utf8(bytes)
+        .ok()
+        .filter(|text| !text.contains('\0')))
+}
+
+fn read_file(args: &Map<String, Value>) -> Result<String, String> {
+    let path = string(args, "path")?;
+    let text = read_text(&resolve(path))?.ok_or(format!("{} is a binary file", path))?;
+    let (start, end) = match (line(args, "start_line"), line(args, "end_line")) {
+        (None, None) => return Ok(cut(text)),
+        (start, end) => (start.unwrap_or(1).max(1), end.unwrap_or(usize::MAX)),
+    };
+    let lines: Vec<&str> = text
+        .lines()
+        .skip(start - 1)
+        .take(end.saturating_sub(start - 1))
+        .collect();
+    Ok(cut(lines.join("\n")))
+}
+
+fn list_dir(args: &Map<String, Value>) -> Result<String, String> {
+    let path = string(args, "path")?;
+    let entries = fs::read_dir(resolve(path)).map_err(|err| format!("{}: {}", path, err))?;
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match entry.path().is_dir() {
+                true => format!("{}/", name),
+                false => name,
+            }
+        })
+        .collect();
+    names.sort();
+    Ok(cut(names.join("\n")))
+}
+
+// Adds the matching lines of every text file under the directory, hidden
+// ones and links are skipped so links back up the tree can't loop
+fn search(dir: &Path, pattern: &str, found: &mut Vec<String>, files: &mut usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| Some((entry.path(), entry.file_type().ok()?)))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    for (path, kind) in entries {
+        if found.len() >= MAX_MATCHES || *files >= MAX_FILES {
+            return;
+        }
+        if kind.is_dir() {
+            search(&path, pattern, found, files);
+        } else if kind.is_file() {
+            *files += 1;
+            search_file(&path, pattern, found);
+        }
+    }
+}
+
+fn search_file(path: &Path, pattern: &str, found: &mut Vec<String>) {
+    if let Ok(Some(text)) = read_text(path) {
+        for (i, line) in text.lines().enumerate() {
+            if line.contains(pattern) && found.len() < MAX_MATCHES {
+                found.push(format!("{}:{}:{}", path.display(), i + 1, line));
+            }
+        }
+    }
+}
+
+fn grep(args: &Map<String, Value>) -> Result<String, String> {
+    let pattern = string(args, "pattern")?;
+    let path = args.get("path").and_then(Value::as_str).unwrap_or(".");
+    if pattern.is_empty() {
+        return Err("the pattern is empty".to_string());
+    }
+    let root = resolve(path);
+    if !root.exists() {
+        return Err(format!("{} doesn't exist", path));
+    }
+    let (mut found, mut files) = (vec![], 0);
+    // The path given is followed even when it is a link
+    if root.is_dir() {
+        search(&root, pattern, &mut found, &mut files);
+    } else {
+        search_file(&root, pattern, &mut found);
+    }
+    let text = match found.len() {
+        0 => "No matches".to_string(),
+        MAX_MATCHES => cut(found.join("\n")) + &format!("\n[first {} matches]", MAX_MATCHES),
+        _ => cut(found.join("\n")),
+    };
+    Ok(match files {
+        MAX_FILES => text + &format!("\n[stopped after {} files]", MAX_FILES),
+        _ => text,
+    })
+}
+
+fn run_shell(args: &Map<String, Value>) -> Result<String, String> {
+    let command = string(args, "command")?;
+    let output = shell::run(command, shell::TIMEOUT, shell::LIMIT)
+        .map_err(|err| format!("can't run {}: {}", command, err))?;
+    Ok(shell::block(command, &output))
+}
+
+/// Runs a call, failures are given back to the model as text
+pub fn run(name: &str, args: &str) -> String {
+    let result = arguments(args).and_then(|args| match name {
+        "read_file" => read_file(&args),
+        "list_dir" => list_dir(&args),
+        "grep" => grep(&args),
+        "shell" => run_shell(&args),
+        _ => Err(format!("there is no {} function", name)),
+    });
+    result.unwrap_or_else(|err| format!("[error: {}]", err))
+}
+
+/// The call on a line, like `read_file path=src/main.rs`
+pub fn describe(name: &str, args: &str) -> String {
+    let Ok(args) = arguments(args) else {
+        return format!("{} {}", name, args.trim());
+    };
+    let mut text = name.to_string();
+    for (key, value) in args {
+        let value = match value {
+            Value::String(value) => value,
+            value => value.to_string(),
+        };
+        text += &format!(" {}={}", key, value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tools::{describe, run, schemas};
+    use serde_json::json;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn tools() {
+        let dir = env::temp_dir().join(format!("gpterm-tools-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        fs::write(dir.join("notes.md"), "run it\n").unwrap();
+        fs::write(dir.join(".git/HEAD"), "run\n").unwrap();
+        fs::write(dir.join("image.png"), [137, 80, 0, 71]).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("src/up")).unwrap();
+        let base = dir.to_str().unwrap();
+        let call = |name: &str, args: serde_json::Value| run(name, &args.to_string());
+
+        let main = format!("{}/src/main.rs", base);
+        assert_eq!(
+            call("read_file", json!({ "path": main })),
+            "fn main() {\n    run();\n}\n"
+        );
+        assert_eq!(
+            call(
+                "read_file",
+                json!({ "path": main, "start_line": 2, "end_line": 3 })
+            ),
+            "    run();\n}"
+        );
+        assert_eq!(
+            call(
+                "read_file",
+                json!({ "path": format!("{}/image.png", base) })
+            ),
+            format!("[error: {}/image.png is a binary file]", base)
+        );
+        assert_eq!(
+            call("list_dir", json!({ "path": base })),
+            ".git/\nimage.png\nnotes.md\nsrc/"
+        );
+        assert_eq!(
+            call("grep", json!({ "pattern": "run", "path": main })),
+            format!("{}:2:    run();", main)
+        );
+        // Hidden directories and links aren't searched
+        assert_eq!(
+            call("grep", json!({ "pattern": "run", "path": base })),
+            format!("{0}/notes.md:1:run it\n{0}/src/main.rs:2:    run();", base)
+        );
+        assert_eq!(
+            call("grep", json!({ "pattern": "nothing", "path": base })),
+            "No matches"
+        );
+        assert_eq!(
+            call("shell", json!({ "command": "echo hi" })),
+            "```console\n$ echo hi\nhi\n```\n[exit code 0]"
+        );
+
+        assert_eq!(run("read_file", "{}"), "[error: missing the path argument]");
+        assert_eq!(
+            run("read_file", "{\"path\""),
+            "[error: the arguments aren't a JSON object: {\"path\"]"
+        );
+        assert_eq!(run("rm", "{}"), "[error: there is no rm function]");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(
+            describe("read_file", r#"{"path":"src/main.rs","start_line":3}"#),
+            "read_file path=src/main.rs start_line=3"
+        );
+        assert_eq!(describe("list_dir", "{\"pa"), "list_dir {\"pa");
+        let names: Vec<_> = schemas()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["read_file", "list_dir", "grep", "shell"]);
+    }
+}